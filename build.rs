@@ -0,0 +1,23 @@
+// Compiles the day 7 and day 9 programs for tests/compiled.rs into
+// OUT_DIR, the same way `cargo run compile` does.
+#[allow(dead_code, unused_imports, clippy::all)]
+#[path = "src/exercises/shared/mod.rs"]
+mod shared;
+
+use shared::compiler::compile;
+use shared::int_code_computer::read_code;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    for (day, name) in [(7, "Day7"), (9, "Day9")].iter() {
+        let data = format!("src/exercises/data/data-day{}.txt", day);
+        println!("cargo:rerun-if-changed={}", data);
+        let code = read_code(&fs::read_to_string(&data).unwrap()).unwrap();
+        let path = Path::new(&out_dir).join(format!("day{}.rs", day));
+        fs::write(path, compile(&code, name)).unwrap();
+    }
+    println!("cargo:rerun-if-changed=src/exercises/shared");
+}
@@ -1,11 +1,94 @@
 use super::super::super::utils;
-use super::super::shared::int_code_computer::IntCodeComputer;
+use super::super::shared::int_code_computer::{IntCodeComputer, IntCodeError};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::thread;
+
+/**
+ * Runs the program of `computer` with `noun` at 1 and `verb` at 2, giving
+ * what ends up at 0.
+ */
+pub fn run_with(computer: &IntCodeComputer, noun: i64, verb: i64) -> Result<i64, IntCodeError> {
+    let mut computer = computer.load_patched(&[(1, noun), (2, verb)])?;
+    computer.process_code()?;
+    Ok(computer.peek(0))
+}
+
+/**
+ * Restores the "1202 program alarm" state before running.
+ */
+pub fn part_1(message: &str) -> i64 {
+    run_with(&IntCodeComputer::new(message).unwrap(), 12, 2).unwrap()
+}
+
+/**
+ * Tries nouns and verbs from 0 to 99 on `threads` threads, each taking
+ * the next noun once it is done with one. Nouns past a pair that was
+ * found are skipped, and of the pairs found the one a plain loop would
+ * have hit first wins.
+ */
+pub fn find_noun_verb(message: &str, target: i64, threads: usize) -> Option<(i64, i64)> {
+    let computer = IntCodeComputer::new(message).unwrap();
+    let next_noun = AtomicI64::new(0);
+    // as 100 * noun + verb
+    let found = AtomicI64::new(i64::MAX);
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let computer = computer.clone();
+            let (next_noun, found) = (&next_noun, &found);
+            scope.spawn(move || loop {
+                let noun = next_noun.fetch_add(1, Ordering::Relaxed);
+                if noun > 99 || noun * 100 > found.load(Ordering::Relaxed) {
+                    break;
+                }
+                // programs that fail for a pair just don't match
+                if let Some(verb) =
+                    (0..100).find(|verb| run_with(&computer, noun, *verb) == Ok(target))
+                {
+                    found.fetch_min(100 * noun + verb, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    match found.into_inner() {
+        i64::MAX => None,
+        pair => Some((pair / 100, pair % 100)),
+    }
+}
+
+pub fn part_2(message: &str) -> Option<i64> {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    find_noun_verb(message, 19690720, threads).map(|(noun, verb)| 100 * noun + verb)
+}
 
 pub fn main() {
     let message = utils::read_file_into_string("./src/exercises/data/data-day2.txt");
-    let mut int_code_computer = IntCodeComputer::new(&message);
-    let result = int_code_computer.process_code();
 
     println!("--- Day 2 ---");
-    println!("answer: {:?}", result.code[0]);
+    println!("answer: {}", part_1(&message));
+    match part_2(&message) {
+        Some(answer) => println!("100 * noun + verb: {}", answer),
+        None => println!("no noun and verb give 19690720"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches_noun_and_verb() {
+        // adds the cells that noun and verb point to
+        let computer = IntCodeComputer::new("1,0,0,0,99,30,40,50").unwrap();
+        assert_eq!(run_with(&computer, 5, 7), Ok(80));
+        assert_eq!(run_with(&computer, 0, 0), Ok(2));
+    }
+
+    #[test]
+    fn finds_the_same_pair_on_any_number_of_threads() {
+        let message = utils::read_file_into_string("./src/exercises/data/data-day2.txt");
+        for threads in [1, 4, 16].iter() {
+            assert_eq!(find_noun_verb(&message, 19690720, *threads), Some((49, 67)));
+        }
+        assert_eq!(find_noun_verb(&message, -1, 4), None);
+    }
 }
@@ -1,33 +1,30 @@
 use super::super::super::utils;
 use super::super::shared::int_code_computer::IntCodeComputer;
+use super::super::shared::network::{Network, Topology};
 use permutator::Permutation;
 
+/**
+ * One amplifier per phase setting, wired into a ring. Without a feedback
+ * loop the first amplifier has already halted by the time the last one
+ * feeds it, which makes no difference to the final signal.
+ */
 pub fn run_amplification_circuit(
   message: &str,
   input_to_amplifier_a: i64,
   phase_settings: &Vec<i64>,
 ) -> i64 {
-  let mut amplifier_a = IntCodeComputer::new(message);
-  let mut amplifier_b = IntCodeComputer::new(message);
-  let mut amplifier_c = IntCodeComputer::new(message);
-  let mut amplifier_d = IntCodeComputer::new(message);
-  let mut amplifier_e = IntCodeComputer::new(message);
-  amplifier_a.add_inputs(vec![phase_settings[0]]);
-  amplifier_b.add_inputs(vec![phase_settings[1]]);
-  amplifier_c.add_inputs(vec![phase_settings[2]]);
-  amplifier_d.add_inputs(vec![phase_settings[3]]);
-  amplifier_e.add_inputs(vec![phase_settings[4]]);
-
-  let mut input_to_amplifier_a = input_to_amplifier_a;
-  while !amplifier_a.is_done {
-    amplifier_a.add_inputs(vec![input_to_amplifier_a]);
-    amplifier_b.add_inputs(vec![amplifier_a.process_code().output[0]]);
-    amplifier_c.add_inputs(vec![amplifier_b.process_code().output[0]]);
-    amplifier_d.add_inputs(vec![amplifier_c.process_code().output[0]]);
-    amplifier_e.add_inputs(vec![amplifier_d.process_code().output[0]]);
-    input_to_amplifier_a = amplifier_e.process_code().output[0];
+  let amplifiers = phase_settings
+    .iter()
+    .map(|_| IntCodeComputer::new(message).unwrap())
+    .collect();
+  let mut network = Network::new(amplifiers, Topology::Ring);
+  for (amplifier, phase_setting) in phase_settings.iter().enumerate() {
+    network.send(amplifier, vec![*phase_setting]);
   }
-  input_to_amplifier_a
+  network.send(0, vec![input_to_amplifier_a]);
+
+  network.run().unwrap();
+  network.last_output(phase_settings.len() - 1).unwrap()
 }
 
 pub fn part_1(message: &str) -> i64 {
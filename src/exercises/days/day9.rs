@@ -2,10 +2,10 @@ use super::super::super::utils;
 use super::super::shared::int_code_computer::IntCodeComputer;
 
 pub fn sensor_boost(message: &str, boost_mode: bool) -> i64 {
-  let mut int_code_computer = IntCodeComputer::new(&message);
+  let mut int_code_computer = IntCodeComputer::new(message).unwrap();
   let mode = if boost_mode { 2 } else { 1 };
   int_code_computer.add_inputs(vec![mode]);
-  int_code_computer.process_code().output[0]
+  int_code_computer.process_code().unwrap().output[0]
 }
 
 pub fn main() {
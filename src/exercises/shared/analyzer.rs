@@ -0,0 +1,559 @@
+use super::disassembler::disassemble_at;
+use super::instruction::{decode, Instruction, Mode, Opcode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/**
+ * Follows the code from `start` through fallthroughs and jumps with an
+ * immediate target. In `strict` mode the whole chain is rejected as soon as
+ * it runs into a word that isn't an instruction or into the middle of an
+ * instruction that is already known, as it's most likely data then.
+ */
+fn trace(
+    code: &[i64],
+    start: usize,
+    known: &BTreeMap<usize, Instruction>,
+    covered: &BTreeSet<usize>,
+    strict: bool,
+) -> Option<BTreeMap<usize, Instruction>> {
+    let mut found = BTreeMap::new();
+    let mut words = BTreeSet::new();
+    let mut queue = vec![start];
+    while let Some(address) = queue.pop() {
+        if known.contains_key(&address) || found.contains_key(&address) {
+            continue;
+        }
+        let instruction = match code.get(address).map(|&word| decode(word, address)) {
+            Some(Ok(instruction)) if address + instruction.opcode.size() <= code.len() => {
+                instruction
+            }
+            _ if strict => return None,
+            _ => continue,
+        };
+        let range = address..address + instruction.opcode.size();
+        if range
+            .clone()
+            .any(|word| covered.contains(&word) || words.contains(&word))
+        {
+            if strict {
+                return None;
+            }
+            continue;
+        }
+        words.extend(range);
+        found.insert(address, instruction);
+
+        let parameter = |index: usize| code[address + index + 1];
+        let next = address + instruction.opcode.size();
+        match instruction.opcode {
+            Opcode::Halt => {}
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                // a constant condition either always jumps or never does
+                let taken = match instruction.modes[0] {
+                    Mode::Immediate => {
+                        Some((parameter(0) != 0) == (instruction.opcode == Opcode::JumpIfTrue))
+                    }
+                    _ => None,
+                };
+                let target = parameter(1);
+                if taken != Some(false) && instruction.modes[1] == Mode::Immediate && target >= 0 {
+                    queue.push(target as usize);
+                }
+                if taken != Some(true) {
+                    queue.push(next);
+                }
+            }
+            _ => queue.push(next),
+        }
+    }
+    Some(found)
+}
+
+/**
+ * Finds the instructions that can run, starting at address 0. Jumps
+ * through memory could go anywhere, so every value in the program that
+ * points into it is tried as a starting point as well. That catches jump
+ * tables and return addresses, while data that merely looks like an
+ * address rarely survives the strict checks of trace().
+ */
+pub fn reachable(code: &[i64]) -> BTreeMap<usize, Instruction> {
+    let mut instructions =
+        trace(code, 0, &BTreeMap::new(), &BTreeSet::new(), false).unwrap_or_default();
+    let mut covered: BTreeSet<usize> = instructions
+        .iter()
+        .flat_map(|(&address, instruction)| address..address + instruction.opcode.size())
+        .collect();
+
+    let starts: BTreeSet<usize> = code
+        .iter()
+        .filter(|&&word| word >= 0)
+        .map(|&word| word as usize)
+        .collect();
+    for start in starts {
+        if let Some(found) = trace(code, start, &instructions, &covered, true) {
+            for (&address, instruction) in found.iter() {
+                covered.extend(address..address + instruction.opcode.size());
+            }
+            instructions.extend(found);
+        }
+    }
+    instructions
+}
+
+/**
+ * The address the instruction at `address` writes to, if it writes
+ * through a position parameter and so is known without running anything.
+ */
+pub fn constant_write(code: &[i64], address: usize, instruction: Instruction) -> Option<usize> {
+    if !instruction.opcode.writes() {
+        return None;
+    }
+    let last = instruction.opcode.parameter_count() - 1;
+    let destination = code[address + last + 1];
+    match instruction.modes[last] {
+        Mode::Position if destination >= 0 => Some(destination as usize),
+        _ => None,
+    }
+}
+
+pub fn constant_writes(
+    code: &[i64],
+    instructions: &BTreeMap<usize, Instruction>,
+) -> BTreeSet<usize> {
+    instructions
+        .iter()
+        .filter_map(|(&address, &instruction)| constant_write(code, address, instruction))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    Fallthrough,
+    Jump,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+/**
+ * A run of instructions that is only entered at the top and only left
+ * at the bottom.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<usize>,
+    pub successors: Vec<Edge>,
+    /**
+     * Whether the block ends in a jump whose target is only known at run
+     * time, so the successors may not be all there is.
+     */
+    pub indirect: bool,
+}
+
+/**
+ * A write through a constant address into a word of an instruction, or
+ * into a word control runs into that isn't an instruction until then.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CodeWrite {
+    pub ip: usize,
+    pub address: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Analysis {
+    pub instructions: BTreeMap<usize, Instruction>,
+    pub blocks: BTreeMap<usize, Block>,
+    pub indirect_jumps: Vec<usize>,
+    pub code_writes: Vec<CodeWrite>,
+    /**
+     * Ranges of addresses, both ends included, that no instruction
+     * covers. That is data, or code that nothing ever jumps to.
+     */
+    pub unreachable: Vec<(usize, usize)>,
+}
+
+/**
+ * Where control can go after the instruction, as far as that is known
+ * without running it.
+ */
+fn successors(code: &[i64], address: usize, instruction: Instruction) -> Vec<Edge> {
+    let next = address + instruction.opcode.size();
+    let fallthrough = Edge {
+        target: next,
+        kind: EdgeKind::Fallthrough,
+    };
+    match instruction.opcode {
+        Opcode::Halt => Vec::new(),
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let mut edges = Vec::new();
+            let taken = condition(code, address, instruction);
+            let target = code[address + 2];
+            if taken != Some(false) && instruction.modes[1] == Mode::Immediate && target >= 0 {
+                edges.push(Edge {
+                    target: target as usize,
+                    kind: EdgeKind::Jump,
+                });
+            }
+            if taken != Some(true) {
+                edges.push(fallthrough);
+            }
+            edges
+        }
+        _ => vec![fallthrough],
+    }
+}
+
+/**
+ * Whether a jump is always or never taken, None if that depends on
+ * memory.
+ */
+fn condition(code: &[i64], address: usize, instruction: Instruction) -> Option<bool> {
+    match instruction.modes[0] {
+        Mode::Immediate => {
+            Some((code[address + 1] != 0) == (instruction.opcode == Opcode::JumpIfTrue))
+        }
+        _ => None,
+    }
+}
+
+fn is_indirect(code: &[i64], address: usize, instruction: Instruction) -> bool {
+    match instruction.opcode {
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            condition(code, address, instruction) != Some(false)
+                && instruction.modes[1] != Mode::Immediate
+        }
+        _ => false,
+    }
+}
+
+/**
+ * Addresses in the program that control reaches but where no instruction
+ * could be decoded, which is where trace() gave up.
+ */
+fn stops(code: &[i64], instructions: &BTreeMap<usize, Instruction>) -> BTreeSet<usize> {
+    instructions
+        .iter()
+        .flat_map(|(&address, &instruction)| successors(code, address, instruction))
+        .map(|edge| edge.target)
+        .filter(|target| *target < code.len() && !instructions.contains_key(target))
+        .collect()
+}
+
+fn is_jump(instruction: Instruction) -> bool {
+    matches!(
+        instruction.opcode,
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse | Opcode::Halt
+    )
+}
+
+/**
+ * Splits the program into basic blocks and finds what stands out about
+ * it. Code is followed from address 0 through immediate jump targets.
+ * Once there is a jump through memory, or the code runs into a word it
+ * writes before getting there, code is also looked for wherever
+ * reachable() finds it, so jump tables, return addresses and whatever
+ * follows a rewritten instruction are covered.
+ * Only writes through a position parameter are checked for landing in
+ * the code, relative ones depend on the run.
+ */
+pub fn analyze(code: &[i64]) -> Analysis {
+    let direct = trace(code, 0, &BTreeMap::new(), &BTreeSet::new(), false).unwrap_or_default();
+    let modified = stops(code, &direct)
+        .intersection(&constant_writes(code, &direct))
+        .next()
+        .is_some();
+    let instructions = if modified
+        || direct
+            .iter()
+            .any(|(&address, &instruction)| is_indirect(code, address, instruction))
+    {
+        reachable(code)
+    } else {
+        direct
+    };
+
+    let edges: BTreeMap<usize, Vec<Edge>> = instructions
+        .iter()
+        .map(|(&address, &instruction)| {
+            let edges = successors(code, address, instruction)
+                .into_iter()
+                .filter(|edge| instructions.contains_key(&edge.target))
+                .collect();
+            (address, edges)
+        })
+        .collect();
+    let targets: BTreeSet<usize> = edges
+        .values()
+        .flatten()
+        .filter(|edge| edge.kind == EdgeKind::Jump)
+        .map(|edge| edge.target)
+        .collect();
+
+    let mut blocks: BTreeMap<usize, Block> = BTreeMap::new();
+    let mut current: Option<usize> = None;
+    let mut previous: Option<(usize, Instruction)> = None;
+    for (&address, &instruction) in instructions.iter() {
+        let continues = match previous {
+            Some((start, before)) => start + before.opcode.size() == address && !is_jump(before),
+            None => false,
+        };
+        let start = match current {
+            Some(start) if continues && !targets.contains(&address) => start,
+            _ => address,
+        };
+        let block = blocks.entry(start).or_insert_with(|| Block {
+            start,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+            indirect: false,
+        });
+        block.instructions.push(address);
+        block.successors = edges[&address].clone();
+        block.indirect = is_indirect(code, address, instruction);
+        current = Some(start);
+        previous = Some((address, instruction));
+    }
+
+    let covered: BTreeSet<usize> = instructions
+        .iter()
+        .flat_map(|(&address, instruction)| address..address + instruction.opcode.size())
+        .collect();
+    let indirect_jumps = instructions
+        .iter()
+        .filter(|(&address, &instruction)| is_indirect(code, address, instruction))
+        .map(|(&address, _)| address)
+        .collect();
+    let stopped = stops(code, &instructions);
+    let code_writes: Vec<CodeWrite> = instructions
+        .iter()
+        .filter_map(|(&ip, &instruction)| {
+            constant_write(code, ip, instruction)
+                .filter(|address| covered.contains(address) || stopped.contains(address))
+                .map(|address| CodeWrite { ip, address })
+        })
+        .collect();
+    let mut unreachable: Vec<(usize, usize)> = Vec::new();
+    for address in (0..code.len()).filter(|address| !covered.contains(address)) {
+        match unreachable.last_mut() {
+            Some((_, end)) if *end + 1 == address => *end = address,
+            _ => unreachable.push((address, address)),
+        }
+    }
+    // what follows an instruction that gets written isn't known to be dead
+    unreachable.retain(|(start, end)| {
+        !code_writes
+            .iter()
+            .any(|write| (*start..=*end).contains(&write.address))
+    });
+
+    Analysis {
+        instructions,
+        blocks,
+        indirect_jumps,
+        code_writes,
+        unreachable,
+    }
+}
+
+impl Analysis {
+    /**
+     * The control-flow graph in Graphviz DOT format. Blocks that get
+     * written to are drawn red, jumps through memory lead to a `?` node.
+     */
+    pub fn to_dot(&self, code: &[i64]) -> String {
+        let mut lines = vec![
+            String::from("digraph intcode {"),
+            String::from("    node [shape=box, fontname=\"monospace\"];"),
+        ];
+        for block in self.blocks.values() {
+            let label: String = block
+                .instructions
+                .iter()
+                .filter_map(|&address| disassemble_at(code, address))
+                .map(|line| format!("{}: {}\\l", line.address, line.kind))
+                .collect();
+            let last = block.instructions[block.instructions.len() - 1];
+            let end = last + self.instructions[&last].opcode.size();
+            let modified = self
+                .code_writes
+                .iter()
+                .any(|write| (block.start..end).contains(&write.address));
+            let color = if modified { ", color=red" } else { "" };
+            lines.push(format!(
+                "    b{} [label=\"{}\"{}];",
+                block.start, label, color
+            ));
+        }
+        for block in self.blocks.values() {
+            for edge in block.successors.iter() {
+                let style = match edge.kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                };
+                lines.push(format!(
+                    "    b{} -> b{}{};",
+                    block.start, edge.target, style
+                ));
+            }
+            if block.indirect {
+                lines.push(format!("    b{} -> unknown [style=dashed];", block.start));
+            }
+        }
+        if !self.indirect_jumps.is_empty() {
+            lines.push(String::from("    unknown [shape=ellipse, label=\"?\"];"));
+        }
+        lines.push(String::from("}"));
+        lines.join("\n") + "\n"
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} instructions in {} blocks",
+            self.instructions.len(),
+            self.blocks.len()
+        )?;
+        for ip in self.indirect_jumps.iter() {
+            writeln!(f, "indirect jump at {}", ip)?;
+        }
+        for write in self.code_writes.iter() {
+            writeln!(f, "write into code at {}: {}", write.ip, write.address)?;
+        }
+        for (start, end) in self.unreachable.iter() {
+            if start == end {
+                writeln!(f, "unreachable {}", start)?;
+            } else {
+                writeln!(f, "unreachable {}..={}", start, end)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::super::int_code_computer::read_code;
+    use super::*;
+    use crate::utils;
+
+    // counts its input down to zero
+    const COUNTDOWN: &str = "
+                IN n
+        loop:   ADD n, #-1, n
+                JT n, #loop
+                OUT n
+                HLT
+        n:      .data 0
+    ";
+
+    #[test]
+    fn splits_blocks_at_jumps_and_targets() {
+        let code = assemble(COUNTDOWN).unwrap();
+        let analysis = analyze(&code);
+        let blocks: Vec<(usize, Vec<usize>, Vec<Edge>)> = analysis
+            .blocks
+            .values()
+            .map(|block| {
+                (
+                    block.start,
+                    block.instructions.clone(),
+                    block.successors.clone(),
+                )
+            })
+            .collect();
+        let edge = |target, kind| Edge { target, kind };
+        assert_eq!(
+            blocks,
+            vec![
+                (0, vec![0], vec![edge(2, EdgeKind::Fallthrough)]),
+                (
+                    2,
+                    vec![2, 6],
+                    vec![edge(2, EdgeKind::Jump), edge(9, EdgeKind::Fallthrough)]
+                ),
+                (9, vec![9, 11], vec![]),
+            ]
+        );
+        assert_eq!(analysis.unreachable, vec![(12, 12)]);
+        assert!(analysis.indirect_jumps.is_empty());
+        assert!(analysis.code_writes.is_empty());
+    }
+
+    #[test]
+    fn flags_indirect_jumps_and_writes_into_code() {
+        let code = assemble(
+            "
+                    ADD #5, #0, hop+1
+            hop:    JT #1, target
+                    HLT
+            target: .data 7
+                    .data 1, 2, 3
+            ",
+        )
+        .unwrap();
+        let analysis = analyze(&code);
+        assert_eq!(
+            analysis.instructions.keys().copied().collect::<Vec<_>>(),
+            vec![0, 4, 7]
+        );
+        assert_eq!(analysis.indirect_jumps, vec![4]);
+        assert_eq!(analysis.code_writes, vec![CodeWrite { ip: 0, address: 5 }]);
+        assert_eq!(analysis.unreachable, vec![(8, 11)]);
+        assert_eq!(
+            analysis.to_string(),
+            "3 instructions in 2 blocks\n\
+             indirect jump at 4\n\
+             write into code at 0: 5\n\
+             unreachable 8..=11\n"
+        );
+    }
+
+    #[test]
+    fn draws_the_graph() {
+        let code = assemble(COUNTDOWN).unwrap();
+        let dot = analyze(&code).to_dot(&code);
+        assert!(dot.starts_with("digraph intcode {\n"));
+        assert!(dot.contains("    b2 [label=\"2: ADD 12, #-1, 12\\l6: JT 12, #2\\l\"];\n"));
+        assert!(dot.contains("    b2 -> b2 [label=\"jump\"];\n    b2 -> b9;\n"));
+        assert!(!dot.contains("unknown"));
+    }
+
+    #[test]
+    fn follows_jumps_and_return_addresses() {
+        let code = assemble(
+            "
+                    ADD #0, #back, [1]
+                    JT #1, #away
+            back:   HLT
+                    .data 1, 2, 3
+            away:   JF #0, [1]
+            ",
+        )
+        .unwrap();
+        let found: Vec<usize> = reachable(&code).keys().copied().collect();
+        assert_eq!(found, vec![0, 4, 7, 11]);
+    }
+
+    #[test]
+    fn looks_past_instructions_that_get_written() {
+        let message = utils::read_file_into_string("./src/exercises/data/data-day5.txt");
+        let code = read_code(&message).unwrap();
+        let analysis = analyze(&code);
+        // ADD 225, 6, 6 turns the word at 6 into an instruction
+        assert_eq!(analysis.code_writes[0], CodeWrite { ip: 2, address: 6 });
+        assert!(analysis.instructions.contains_key(&10));
+        assert!(analysis.instructions.len() > 100);
+        assert!(analysis
+            .unreachable
+            .iter()
+            .all(|(start, end)| !(*start..=*end).contains(&6)));
+    }
+}
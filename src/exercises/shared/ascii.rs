@@ -0,0 +1,169 @@
+use super::int_code_computer::{IntCodeComputer, IntCodeError, RunState};
+use std::io::{self, BufRead, Write};
+
+/**
+ * Wraps an IntCodeComputer that talks in text: every output up to 127 is
+ * a character and inputs are lines of character codes ending in a
+ * newline. Anything outside the ASCII range is kept apart as a numeric
+ * result, which is how these programs usually report their answer.
+ */
+pub struct AsciiComputer {
+    computer: IntCodeComputer,
+    values: Vec<i64>,
+}
+
+impl AsciiComputer {
+    pub fn new(computer: IntCodeComputer) -> Self {
+        Self {
+            computer,
+            values: Vec::new(),
+        }
+    }
+
+    pub fn computer(&self) -> &IntCodeComputer {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut IntCodeComputer {
+        &mut self.computer
+    }
+
+    pub fn into_inner(self) -> IntCodeComputer {
+        self.computer
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.computer.is_done
+    }
+
+    /**
+     * Queues the characters of `line` followed by a newline.
+     */
+    pub fn send_line(&mut self, line: &str) {
+        let mut codes: Vec<i64> = line.bytes().map(i64::from).collect();
+        codes.push(i64::from(b'\n'));
+        self.computer.add_inputs(codes);
+    }
+
+    /**
+     * Runs until the program waits for input or halts and returns the
+     * text it printed on the way.
+     */
+    pub fn read_until_prompt(&mut self) -> Result<String, IntCodeError> {
+        self.read_until(None)
+    }
+
+    /**
+     * Like read_until_prompt(), but also stops as soon as the text ends
+     * with `marker`.
+     */
+    pub fn read_until_marker(&mut self, marker: &str) -> Result<String, IntCodeError> {
+        self.read_until(Some(marker))
+    }
+
+    fn read_until(&mut self, marker: Option<&str>) -> Result<String, IntCodeError> {
+        let mut text = String::new();
+        while let RunState::Output(value) = self.computer.run()? {
+            match value {
+                0..=127 => text.push(value as u8 as char),
+                _ => self.values.push(value),
+            }
+            if let Some(marker) = marker {
+                if text.ends_with(marker) {
+                    break;
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    /**
+     * The outputs that weren't characters, in the order they were printed.
+     */
+    pub fn values(&self) -> &[i64] {
+        &self.values
+    }
+
+    /**
+     * Plays the program in a terminal: its text goes to `output` and every
+     * line read from `input` is sent to it, until the program halts or
+     * the input runs out.
+     */
+    pub fn interact<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            let printed = self.values.len();
+            match self.read_until_prompt() {
+                Ok(text) => write!(output, "{}", text)?,
+                Err(error) => {
+                    writeln!(output, "error: {}", error)?;
+                    break;
+                }
+            }
+            for value in self.values[printed..].iter() {
+                writeln!(output, "[{}]", value)?;
+            }
+            output.flush()?;
+            if self.is_done() {
+                break;
+            }
+            match lines.next() {
+                Some(line) => self.send_line(&line?),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble_to_string;
+    use super::*;
+
+    // prints a "> " prompt and echoes every line it reads
+    const ECHO: &str = "
+        prompt: OUT #62
+                OUT #32
+        read:   IN char
+                OUT char
+                EQ char, #10, done
+                JF done, #read
+                JT #1, #prompt
+        char:   .data 0
+        done:   .data 0
+    ";
+
+    fn echo() -> AsciiComputer {
+        let code = assemble_to_string(ECHO).unwrap();
+        AsciiComputer::new(IntCodeComputer::new(&code).unwrap())
+    }
+
+    #[test]
+    fn sends_lines_and_reads_prompts() {
+        let mut comp = echo();
+        assert_eq!(comp.read_until_prompt(), Ok(String::from("> ")));
+        comp.send_line("hello");
+        comp.send_line("there");
+        assert_eq!(comp.read_until_marker("\n"), Ok(String::from("hello\n")));
+        assert_eq!(comp.read_until_prompt(), Ok(String::from("> there\n> ")));
+    }
+
+    #[test]
+    fn keeps_large_values_apart() {
+        let mut comp = AsciiComputer::new(
+            IntCodeComputer::new("104,72,104,105,104,10,104,19349722,99").unwrap(),
+        );
+        assert_eq!(comp.read_until_prompt(), Ok(String::from("Hi\n")));
+        assert_eq!(comp.values(), &[19349722]);
+        assert!(comp.is_done());
+    }
+
+    #[test]
+    fn interacts_line_by_line() {
+        let mut comp = echo();
+        let mut output = Vec::new();
+        comp.interact("ab\ncd\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> ab\n> cd\n> ");
+    }
+}
@@ -0,0 +1,400 @@
+use super::instruction::{Mode, Opcode};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AssembleError {
+    UnknownMnemonic {
+        line: usize,
+        mnemonic: String,
+    },
+    OperandCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    InvalidOperand {
+        line: usize,
+        operand: String,
+    },
+    ImmediateWrite {
+        line: usize,
+    },
+    DuplicateLabel {
+        line: usize,
+        label: String,
+    },
+    UnknownLabel {
+        line: usize,
+        label: String,
+    },
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {}: unknown mnemonic {:?}", line, mnemonic)
+            }
+            AssembleError::OperandCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} operands, found {}",
+                line, expected, found
+            ),
+            AssembleError::InvalidOperand { line, operand } => {
+                write!(f, "line {}: invalid operand {:?}", line, operand)
+            }
+            AssembleError::ImmediateWrite { line } => {
+                write!(f, "line {}: cannot write to an immediate operand", line)
+            }
+            AssembleError::DuplicateLabel { line, label } => {
+                write!(f, "line {}: label {:?} is already defined", line, label)
+            }
+            AssembleError::UnknownLabel { line, label } => {
+                write!(f, "line {}: unknown label {:?}", line, label)
+            }
+        }
+    }
+}
+
+impl Error for AssembleError {}
+
+/**
+ * A number, a label, or a label with an offset.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Expression {
+    label: Option<String>,
+    offset: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Statement {
+    Instruction {
+        opcode: Opcode,
+        operands: Vec<(Mode, Expression)>,
+    },
+    Data(Vec<Expression>),
+}
+
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_expression(text: &str, line: usize) -> Result<Expression, AssembleError> {
+    let invalid = || AssembleError::InvalidOperand {
+        line,
+        operand: text.to_string(),
+    };
+
+    if let Ok(offset) = text.parse::<i64>() {
+        return Ok(Expression {
+            label: None,
+            offset,
+        });
+    }
+
+    let (label, offset) = match text.find(['+', '-']) {
+        Some(index) => {
+            let offset = text[index..]
+                .replace(' ', "")
+                .parse::<i64>()
+                .map_err(|_| invalid())?;
+            (text[..index].trim(), offset)
+        }
+        None => (text, 0),
+    };
+    if !is_label(label) {
+        return Err(invalid());
+    }
+    Ok(Expression {
+        label: Some(label.to_string()),
+        offset,
+    })
+}
+
+fn parse_operand(text: &str, line: usize) -> Result<(Mode, Expression), AssembleError> {
+    if let Some(rest) = text.strip_prefix('#') {
+        Ok((Mode::Immediate, parse_expression(rest.trim(), line)?))
+    } else if text.starts_with('[') && text.ends_with(']') {
+        let inner = text[1..text.len() - 1].trim();
+        Ok((Mode::Relative, parse_expression(inner, line)?))
+    } else {
+        Ok((Mode::Position, parse_expression(text, line)?))
+    }
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        Vec::new()
+    } else {
+        text.split(',').map(|operand| operand.trim()).collect()
+    }
+}
+
+fn parse_statement(text: &str, line: usize) -> Result<Statement, AssembleError> {
+    let (name, rest) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    };
+
+    if name.eq_ignore_ascii_case(".data") {
+        let values = split_operands(rest)
+            .into_iter()
+            .map(|value| parse_expression(value, line))
+            .collect::<Result<Vec<Expression>, AssembleError>>()?;
+        return Ok(Statement::Data(values));
+    }
+
+    let opcode = Opcode::from_mnemonic(name).ok_or(AssembleError::UnknownMnemonic {
+        line,
+        mnemonic: name.to_string(),
+    })?;
+    let operands = split_operands(rest)
+        .into_iter()
+        .map(|operand| parse_operand(operand, line))
+        .collect::<Result<Vec<(Mode, Expression)>, AssembleError>>()?;
+
+    if operands.len() != opcode.parameter_count() {
+        return Err(AssembleError::OperandCount {
+            line,
+            expected: opcode.parameter_count(),
+            found: operands.len(),
+        });
+    }
+    if opcode.writes() && operands[operands.len() - 1].0 == Mode::Immediate {
+        return Err(AssembleError::ImmediateWrite { line });
+    }
+    Ok(Statement::Instruction { opcode, operands })
+}
+
+fn resolve(
+    expression: &Expression,
+    labels: &HashMap<String, usize>,
+    line: usize,
+) -> Result<i64, AssembleError> {
+    match &expression.label {
+        Some(label) => match labels.get(label) {
+            Some(address) => (*address as i64)
+                .checked_add(expression.offset)
+                .ok_or_else(|| AssembleError::InvalidOperand {
+                    line,
+                    operand: format!("{}{:+}", label, expression.offset),
+                }),
+            None => Err(AssembleError::UnknownLabel {
+                line,
+                label: label.clone(),
+            }),
+        },
+        None => Ok(expression.offset),
+    }
+}
+
+fn mode_code(mode: Mode) -> i64 {
+    match mode {
+        Mode::Position => 0,
+        Mode::Immediate => 1,
+        Mode::Relative => 2,
+    }
+}
+
+/**
+ * Compiles a small assembly language into Intcode.
+ *
+ * ```text
+ * ; count down from the input to 1
+ *         IN n
+ * loop:   OUT n
+ *         ADD n, #-1, n
+ *         JT n, #loop
+ *         HLT
+ * n:      .data 0
+ * ```
+ *
+ * Mnemonics are the ones the disassembler prints. Operands are position
+ * mode by default, #x for immediate and [x] for relative mode, where x is
+ * a number, a label, or a label plus or minus a number. `.data` emits its
+ * operands as raw words and `;` starts a comment.
+ */
+pub fn assemble(source: &str) -> Result<Vec<i64>, AssembleError> {
+    // first pass: parse every line and find out where the labels end up
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut statements: Vec<(usize, Statement)> = Vec::new();
+    let mut address = 0;
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = match text.find(';') {
+            Some(comment) => &text[..comment],
+            None => text,
+        }
+        .trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_label(label) {
+                break;
+            }
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(AssembleError::DuplicateLabel {
+                    line,
+                    label: label.to_string(),
+                });
+            }
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(text, line)?;
+        address += match &statement {
+            Statement::Instruction { opcode, .. } => opcode.size(),
+            Statement::Data(values) => values.len(),
+        };
+        statements.push((line, statement));
+    }
+
+    // second pass: emit words now that every label has an address
+    let mut code = Vec::with_capacity(address);
+    for (line, statement) in statements.iter() {
+        match statement {
+            Statement::Instruction { opcode, operands } => {
+                let mut word = opcode.code();
+                let mut multiplier = 100;
+                for (mode, _) in operands.iter() {
+                    word += mode_code(*mode) * multiplier;
+                    multiplier *= 10;
+                }
+                code.push(word);
+                for (_, expression) in operands.iter() {
+                    code.push(resolve(expression, &labels, *line)?);
+                }
+            }
+            Statement::Data(values) => {
+                for expression in values.iter() {
+                    code.push(resolve(expression, &labels, *line)?);
+                }
+            }
+        }
+    }
+    Ok(code)
+}
+
+/**
+ * Assembles straight into the comma separated format that
+ * IntCodeComputer::new reads.
+ */
+pub fn assemble_to_string(source: &str) -> Result<String, AssembleError> {
+    let code = assemble(source)?;
+    let words: Vec<String> = code.iter().map(|word| word.to_string()).collect();
+    Ok(words.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::disassembler;
+    use super::*;
+
+    #[test]
+    fn assembles_modes_and_labels() {
+        let source = "
+            ; count down from the input to 1
+                    IN n
+            loop:   OUT n
+                    ADD n, #-1, n
+                    JT n, #loop
+                    HLT
+            n:      .data 0
+        ";
+
+        assert_eq!(
+            assemble_to_string(source),
+            Ok(String::from("3,12,4,12,1001,12,-1,12,1005,12,2,99,0"))
+        );
+    }
+
+    #[test]
+    fn assembles_relative_operands_and_offsets() {
+        let source = "
+            start: ARB #table
+                   OUT [1]
+                   JF #0, #start+7
+            table: .data 7, 8, start-1
+                   hlt
+        ";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![109, 7, 204, 1, 1106, 0, 7, 7, 8, -1, 99])
+        );
+    }
+
+    #[test]
+    fn round_trips_through_the_disassembler() {
+        let code = vec![21101, 5, -1, 7, 204, 3, 1005, 0, 12, 99, 0, -4];
+        let source: Vec<String> = disassembler::disassemble(&code)
+            .iter()
+            .map(|line| match &line.kind {
+                disassembler::LineKind::Data(value) => format!(".data {}", value),
+                kind => kind.to_string(),
+            })
+            .collect();
+
+        assert_eq!(assemble(&source.join("\n")), Ok(code));
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        assert_eq!(
+            assemble("HLT\nNOP"),
+            Err(AssembleError::UnknownMnemonic {
+                line: 2,
+                mnemonic: String::from("NOP"),
+            })
+        );
+        assert_eq!(
+            assemble("ADD 1, 2"),
+            Err(AssembleError::OperandCount {
+                line: 1,
+                expected: 3,
+                found: 2,
+            })
+        );
+        assert_eq!(
+            assemble("IN #4"),
+            Err(AssembleError::ImmediateWrite { line: 1 })
+        );
+        assert_eq!(
+            assemble("a: HLT\na: HLT"),
+            Err(AssembleError::DuplicateLabel {
+                line: 2,
+                label: String::from("a"),
+            })
+        );
+        assert_eq!(
+            assemble("JT #1, #nowhere"),
+            Err(AssembleError::UnknownLabel {
+                line: 1,
+                label: String::from("nowhere"),
+            })
+        );
+        assert_eq!(
+            assemble("HLT\nend: JT #1, #end+9223372036854775807"),
+            Err(AssembleError::InvalidOperand {
+                line: 2,
+                operand: String::from("end+9223372036854775807"),
+            })
+        );
+    }
+}
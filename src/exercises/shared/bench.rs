@@ -0,0 +1,495 @@
+use super::instruction::{decode, Instruction, Mode, Opcode};
+use super::int_code_computer::{read_code, IntCodeComputer, IntCodeError, RunState};
+use super::memory::Memory;
+use super::network::NetworkError;
+use permutator::Permutation;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/**
+ * Which interpreter a workload runs on.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Interpreter {
+    /**
+     * The dispatch from before the decode cache, see Baseline.
+     */
+    Baseline,
+    /**
+     * IntCodeComputer, with its program decoded up front.
+     */
+    Current,
+}
+
+/**
+ * The interpreter loop as it was before the decode cache: every time an
+ * instruction runs it is decoded from its first word again and
+ * get_positions works out where its parameters point. Overflows are
+ * checked the way IntCodeComputer checks them, so both can run the same
+ * programs. Only kept as the baseline to time IntCodeComputer against.
+ */
+pub struct Baseline {
+    code: Memory,
+    inputs: VecDeque<i64>,
+    instruction_pointer: usize,
+    pub is_done: bool,
+    output: Vec<i64>,
+    relative_base: i64,
+    steps: u64,
+}
+
+impl Baseline {
+    pub fn new(program: Vec<i64>) -> Self {
+        Self {
+            code: Memory::new(program),
+            inputs: VecDeque::new(),
+            instruction_pointer: 0,
+            is_done: false,
+            output: Vec::new(),
+            relative_base: 0,
+            steps: 0,
+        }
+    }
+
+    pub fn add_inputs(&mut self, inputs: Vec<i64>) {
+        self.inputs.extend(inputs);
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /**
+     * Runs until the program halts or waits for input, returning the
+     * outputs produced along the way.
+     */
+    pub fn process_code(&mut self) -> Result<Vec<i64>, IntCodeError> {
+        self.is_done = false;
+        self.output = Vec::new();
+        loop {
+            let ip = self.instruction_pointer;
+            let instruction = decode(self.code.get(ip), ip)?;
+            match instruction.opcode {
+                Opcode::Add => {
+                    let [address_1, address_2, address_3] = self.get_positions(instruction)?;
+                    let value = self
+                        .code
+                        .get(address_1)
+                        .checked_add(self.code.get(address_2));
+                    self.write(address_3, self.checked(value)?);
+                    self.instruction_pointer += 4;
+                }
+                Opcode::Multiply => {
+                    let [address_1, address_2, address_3] = self.get_positions(instruction)?;
+                    let value = self
+                        .code
+                        .get(address_1)
+                        .checked_mul(self.code.get(address_2));
+                    self.write(address_3, self.checked(value)?);
+                    self.instruction_pointer += 4;
+                }
+                Opcode::Input => {
+                    let value = match self.inputs.front() {
+                        Some(value) => *value,
+                        // wait for new input
+                        None => break,
+                    };
+                    let [address_1, _, _] = self.get_positions(instruction)?;
+                    self.write(address_1, value);
+                    self.inputs.pop_front();
+                    self.instruction_pointer += 2;
+                }
+                Opcode::Output => {
+                    let [address_1, _, _] = self.get_positions(instruction)?;
+                    self.output.push(self.code.get(address_1));
+                    self.instruction_pointer += 2;
+                }
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    let [address_1, address_2, _] = self.get_positions(instruction)?;
+                    let jump_if_true = instruction.opcode == Opcode::JumpIfTrue;
+                    if (self.code.get(address_1) != 0) == jump_if_true {
+                        self.instruction_pointer = self.address(self.code.get(address_2))?;
+                    } else {
+                        self.instruction_pointer += 3;
+                    }
+                }
+                Opcode::LessThan | Opcode::Equals => {
+                    let [address_1, address_2, address_3] = self.get_positions(instruction)?;
+                    let (a, b) = (self.code.get(address_1), self.code.get(address_2));
+                    let holds = if instruction.opcode == Opcode::LessThan {
+                        a < b
+                    } else {
+                        a == b
+                    };
+                    self.write(address_3, holds as i64);
+                    self.instruction_pointer += 4;
+                }
+                Opcode::AdjustRelativeBase => {
+                    let [address_1, _, _] = self.get_positions(instruction)?;
+                    let value = self.relative_base.checked_add(self.code.get(address_1));
+                    self.relative_base = self.checked(value)?;
+                    self.instruction_pointer += 2;
+                }
+                Opcode::Halt => {
+                    self.is_done = true;
+                    break;
+                }
+            }
+            self.steps += 1;
+        }
+        Ok(self.output.clone())
+    }
+
+    fn checked(&self, value: Option<i64>) -> Result<i64, IntCodeError> {
+        value.ok_or(IntCodeError::Overflow {
+            ip: self.instruction_pointer,
+        })
+    }
+
+    fn address(&self, value: i64) -> Result<usize, IntCodeError> {
+        if value < 0 {
+            return Err(IntCodeError::NegativeAddress {
+                ip: self.instruction_pointer,
+                address: value,
+            });
+        }
+        Ok(value as usize)
+    }
+
+    fn get_positions(&self, instruction: Instruction) -> Result<[usize; 3], IntCodeError> {
+        let ip = self.instruction_pointer;
+        let parameter_count = instruction.opcode.parameter_count();
+        let mut positions: [usize; 3] = [0; 3];
+        for (index, position) in positions.iter_mut().enumerate().take(parameter_count) {
+            let parameter = self.code.get(ip + index + 1);
+            *position = match instruction.modes[index] {
+                Mode::Position => self.address(parameter)?,
+                Mode::Immediate => ip + index + 1,
+                Mode::Relative => {
+                    self.address(self.checked(parameter.checked_add(self.relative_base))?)?
+                }
+            }
+        }
+        Ok(positions)
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        // there is no limit, so this can't fail
+        let _ = self.code.set(address, value);
+    }
+}
+
+/**
+ * How long a program took on the Baseline dispatch and on
+ * IntCodeComputer as it is now, which runs its instructions from the
+ * decode cache.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Comparison {
+    pub name: String,
+    pub runs: u32,
+    pub steps: u64,
+    pub baseline: Duration,
+    pub current: Duration,
+}
+
+impl Comparison {
+    pub fn speedup(&self) -> f64 {
+        self.baseline.as_secs_f64() / self.current.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} instructions x {} runs, baseline {:?}, current {:?} ({:.2}x)",
+            self.name,
+            self.steps,
+            self.runs,
+            self.baseline,
+            self.current,
+            self.speedup()
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BenchError<E> {
+    Workload(E),
+    /**
+     * The two interpreters disagree, so one of them is broken.
+     */
+    Mismatch {
+        name: String,
+        baseline: Vec<i64>,
+        current: Vec<i64>,
+    },
+}
+
+impl<E: fmt::Display> fmt::Display for BenchError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BenchError::Workload(error) => write!(f, "{}", error),
+            BenchError::Mismatch {
+                name,
+                baseline,
+                current,
+            } => write!(
+                f,
+                "{} gives different output than the baseline: {:?} instead of {:?}",
+                name, current, baseline
+            ),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for BenchError<E> {}
+
+fn time_runs<E, F>(
+    workload: &mut F,
+    runs: u32,
+    interpreter: Interpreter,
+) -> Result<(Duration, u64, Vec<i64>), E>
+where
+    F: FnMut(Interpreter) -> Result<(u64, Vec<i64>), E>,
+{
+    let mut elapsed = Duration::default();
+    let mut result = (0, Vec::new());
+    for _ in 0..runs {
+        let start = Instant::now();
+        result = workload(interpreter)?;
+        elapsed += start.elapsed();
+    }
+    Ok((elapsed, result.0, result.1))
+}
+
+/**
+ * Times `workload` `runs` times on each interpreter. The workload gets
+ * told which one to run on and returns how many instructions it ran and
+ * its output. The two have to agree on the output.
+ */
+pub fn compare_with<E, F>(
+    name: &str,
+    runs: u32,
+    mut workload: F,
+) -> Result<Comparison, BenchError<E>>
+where
+    F: FnMut(Interpreter) -> Result<(u64, Vec<i64>), E>,
+{
+    let (baseline, steps, expected) =
+        time_runs(&mut workload, runs, Interpreter::Baseline).map_err(BenchError::Workload)?;
+    let (current, _, output) =
+        time_runs(&mut workload, runs, Interpreter::Current).map_err(BenchError::Workload)?;
+    if output != expected {
+        return Err(BenchError::Mismatch {
+            name: name.to_string(),
+            baseline: expected,
+            current: output,
+        });
+    }
+
+    Ok(Comparison {
+        name: name.to_string(),
+        runs,
+        steps,
+        baseline,
+        current,
+    })
+}
+
+/**
+ * Runs a single program to completion `runs` times on each interpreter.
+ * The program is parsed and decoded once up front, loading it into a
+ * fresh computer is timed.
+ */
+pub fn compare(
+    name: &str,
+    code: &str,
+    inputs: &[i64],
+    runs: u32,
+) -> Result<Comparison, BenchError<IntCodeError>> {
+    let program = read_code(code).map_err(BenchError::Workload)?;
+    let template = IntCodeComputer::from_program(program.clone());
+    compare_with(name, runs, |interpreter| match interpreter {
+        Interpreter::Baseline => {
+            let mut computer = Baseline::new(program.clone());
+            computer.add_inputs(inputs.to_vec());
+            let output = computer.process_code()?;
+            Ok((computer.steps(), output))
+        }
+        Interpreter::Current => {
+            let mut computer = template.clone();
+            computer.add_inputs(inputs.to_vec());
+            let output = computer.process_code()?.output;
+            Ok((computer.steps(), output))
+        }
+    })
+}
+
+/**
+ * What the feedback loop of feedback_circuits needs from an interpreter,
+ * so both run the same loop.
+ */
+trait Amplifier {
+    fn feed(&mut self, signals: Vec<i64>) -> Result<Vec<i64>, IntCodeError>;
+    fn is_done(&self) -> bool;
+    fn steps(&self) -> u64;
+}
+
+impl Amplifier for Baseline {
+    fn feed(&mut self, signals: Vec<i64>) -> Result<Vec<i64>, IntCodeError> {
+        self.add_inputs(signals);
+        self.process_code()
+    }
+
+    fn is_done(&self) -> bool {
+        self.is_done
+    }
+
+    fn steps(&self) -> u64 {
+        self.steps
+    }
+}
+
+impl Amplifier for IntCodeComputer {
+    fn feed(&mut self, signals: Vec<i64>) -> Result<Vec<i64>, IntCodeError> {
+        self.add_inputs(signals);
+        // process_code() would copy the whole memory every time around
+        let mut output = Vec::new();
+        while let RunState::Output(value) = self.run()? {
+            output.push(value);
+        }
+        Ok(output)
+    }
+
+    fn is_done(&self) -> bool {
+        self.is_done
+    }
+
+    fn steps(&self) -> u64 {
+        IntCodeComputer::steps(self)
+    }
+}
+
+/**
+ * The last signal the amplifiers send back around, each already given its
+ * phase setting, and how many instructions they took together.
+ */
+fn circuit<A: Amplifier>(mut amplifiers: Vec<A>) -> Result<(u64, i64), NetworkError> {
+    let mut signals = vec![0];
+    let mut last = 0;
+    let count = amplifiers.len();
+    for node in (0..count).cycle() {
+        let amplifier = &mut amplifiers[node];
+        signals = amplifier
+            .feed(signals)
+            .map_err(|error| NetworkError::Machine { node, error })?;
+        if node == count - 1 {
+            last = signals.last().copied().unwrap_or(last);
+            if amplifier.is_done() {
+                break;
+            }
+        }
+        if signals.is_empty() && !amplifier.is_done() {
+            return Err(NetworkError::Deadlock {
+                waiting: vec![node],
+            });
+        }
+    }
+    let steps = amplifiers.iter().map(Amplifier::steps).sum();
+    Ok((steps, last))
+}
+
+/**
+ * Every phase setting of a day 7 amplifier program wired in a feedback
+ * loop, as one workload for compare_with. The program is only parsed and
+ * decoded once, so mostly the amplifiers themselves get timed. They only
+ * run a few dozen instructions each and rewrite their own code before
+ * running it, which is where the decode cache does worst.
+ */
+pub fn feedback_circuits(
+    code: &str,
+    interpreter: Interpreter,
+) -> Result<(u64, Vec<i64>), NetworkError> {
+    let program = read_code(code).map_err(|error| NetworkError::Machine { node: 0, error })?;
+    let template = IntCodeComputer::from_program(program.clone());
+    let mut steps = 0;
+    let mut signals = Vec::new();
+    for phase_settings in [5, 6, 7, 8, 9].permutation() {
+        let (circuit_steps, signal) = match interpreter {
+            Interpreter::Baseline => circuit(
+                phase_settings
+                    .iter()
+                    .map(|phase_setting| {
+                        let mut amplifier = Baseline::new(program.clone());
+                        amplifier.add_inputs(vec![*phase_setting]);
+                        amplifier
+                    })
+                    .collect(),
+            )?,
+            Interpreter::Current => circuit(
+                phase_settings
+                    .iter()
+                    .map(|phase_setting| {
+                        let mut amplifier = template.clone();
+                        amplifier.add_inputs(vec![*phase_setting]);
+                        amplifier
+                    })
+                    .collect(),
+            )?,
+        };
+        steps += circuit_steps;
+        signals.push(signal);
+    }
+    Ok((steps, signals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_both_interpreters() {
+        // counts down from its input
+        let comparison = compare(
+            "countdown",
+            "3,12,4,12,1001,12,-1,12,1005,12,2,99,0",
+            &[3],
+            2,
+        )
+        .unwrap();
+        assert_eq!(comparison.steps, 10);
+        assert_eq!(comparison.runs, 2);
+    }
+
+    #[test]
+    fn baseline_runs_the_puzzles() {
+        let day7 = crate::utils::read_file_into_string("./src/exercises/data/data-day7.txt");
+        let (_, baseline) = feedback_circuits(&day7, Interpreter::Baseline).unwrap();
+        let (_, current) = feedback_circuits(&day7, Interpreter::Current).unwrap();
+        assert_eq!(baseline, current);
+
+        let day9 = crate::utils::read_file_into_string("./src/exercises/data/data-day9.txt");
+        let mut computer = Baseline::new(read_code(&day9).unwrap());
+        computer.add_inputs(vec![1]);
+        assert_eq!(computer.process_code(), Ok(vec![4261108180]));
+    }
+
+    #[test]
+    fn disagreeing_outputs_are_an_error() {
+        let comparison = compare_with("broken", 1, |interpreter| {
+            Ok::<_, IntCodeError>((1, vec![(interpreter == Interpreter::Current) as i64]))
+        });
+        assert_eq!(
+            comparison,
+            Err(BenchError::Mismatch {
+                name: String::from("broken"),
+                baseline: vec![0],
+                current: vec![1]
+            })
+        );
+    }
+}
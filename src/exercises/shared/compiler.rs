@@ -0,0 +1,405 @@
+use super::analyzer::{constant_writes, reachable};
+use super::instruction::{Instruction, Mode, Opcode};
+use std::collections::BTreeSet;
+
+/**
+ * Turns address sets into a pattern like `0..=3 | 7 | 9..=12`.
+ */
+fn ranges(addresses: &BTreeSet<usize>) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &address in addresses {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == address => *end = address,
+            _ => ranges.push((address, address)),
+        }
+    }
+    let patterns: Vec<String> = ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}..={}", start, end)
+            }
+        })
+        .collect();
+    patterns.join(" | ")
+}
+
+struct Arm<'a> {
+    code: &'a [i64],
+    address: usize,
+    instruction: Instruction,
+    written: &'a BTreeSet<usize>,
+}
+
+impl<'a> Arm<'a> {
+    /**
+     * The raw parameter, read from memory if the program may overwrite it.
+     */
+    fn parameter(&self, index: usize) -> String {
+        let address = self.address + index + 1;
+        if self.written.contains(&address) {
+            format!("self.memory.get({})", address)
+        } else {
+            format!("({})", self.code[address])
+        }
+    }
+
+    fn constant(&self, index: usize) -> Option<i64> {
+        let address = self.address + index + 1;
+        if self.written.contains(&address) {
+            None
+        } else {
+            Some(self.code[address])
+        }
+    }
+
+    fn address(&self, index: usize) -> String {
+        match (self.instruction.modes[index], self.constant(index)) {
+            (Mode::Position, Some(value)) if value >= 0 => value.to_string(),
+            (Mode::Position, _) => format!("address!(self, {})", self.parameter(index)),
+            (Mode::Relative, _) => format!(
+                "address!(self, checked!(self, i64::checked_add({}, self.relative_base)))",
+                self.parameter(index)
+            ),
+            (Mode::Immediate, _) => (self.address + index + 1).to_string(),
+        }
+    }
+
+    fn read(&self, index: usize) -> String {
+        match self.instruction.modes[index] {
+            Mode::Immediate => self.parameter(index),
+            _ => format!("self.memory.get({})", self.address(index)),
+        }
+    }
+
+    /**
+     * Constant destinations are never part of the frozen code, anything
+     * else has to be checked before writing.
+     */
+    fn destination(&self, index: usize) -> String {
+        match (self.instruction.modes[index], self.constant(index)) {
+            (Mode::Position, Some(value)) if value >= 0 => value.to_string(),
+            _ => format!("writable!(self, {})", self.address(index)),
+        }
+    }
+
+    fn body(&self) -> Vec<String> {
+        let next = self.address + self.instruction.opcode.size();
+        let mut lines = Vec::new();
+        match self.instruction.opcode {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+                lines.push(format!("let a = {};", self.read(0)));
+                lines.push(format!("let b = {};", self.read(1)));
+                lines.push(format!("let destination = {};", self.destination(2)));
+                lines.push(match self.instruction.opcode {
+                    Opcode::Add => String::from(
+                        "self.write(destination, checked!(self, i64::checked_add(a, b)));",
+                    ),
+                    Opcode::Multiply => String::from(
+                        "self.write(destination, checked!(self, i64::checked_mul(a, b)));",
+                    ),
+                    Opcode::LessThan => String::from("self.write(destination, (a < b) as i64);"),
+                    _ => String::from("self.write(destination, (a == b) as i64);"),
+                });
+            }
+            Opcode::Input => {
+                lines.push(String::from("if self.inputs.is_empty() {"));
+                lines.push(String::from("    return Ok(RunState::NeedsInput);"));
+                lines.push(String::from("}"));
+                lines.push(format!("let destination = {};", self.destination(0)));
+                lines.push(String::from(
+                    "let value = self.inputs.pop_front().unwrap();",
+                ));
+                lines.push(String::from("self.write(destination, value);"));
+            }
+            Opcode::Output => {
+                lines.push(format!("let value = {};", self.read(0)));
+                lines.push(String::from("self.output.push(value);"));
+                lines.push(format!("self.ip = {};", next));
+                lines.push(String::from("self.steps += 1;"));
+                lines.push(String::from("return Ok(RunState::Output(value));"));
+                return lines;
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let comparison = match self.instruction.opcode {
+                    Opcode::JumpIfTrue => "!=",
+                    _ => "==",
+                };
+                lines.push(format!("let condition = {};", self.read(0)));
+                lines.push(format!("let target = {};", self.read(1)));
+                lines.push(format!("self.ip = if condition {} 0 {{", comparison));
+                lines.push(String::from("    address!(self, target)"));
+                lines.push(String::from("} else {"));
+                lines.push(format!("    {}", next));
+                lines.push(String::from("};"));
+                lines.push(String::from("self.steps += 1;"));
+                return lines;
+            }
+            Opcode::AdjustRelativeBase => {
+                lines.push(format!(
+                    "self.relative_base = checked!(self, i64::checked_add(self.relative_base, {}));",
+                    self.read(0)
+                ));
+            }
+            Opcode::Halt => {
+                lines.push(String::from("self.is_done = true;"));
+                lines.push(String::from("return Ok(RunState::Halted);"));
+                return lines;
+            }
+        }
+        lines.push(format!("self.ip = {};", next));
+        lines.push(String::from("self.steps += 1;"));
+        lines
+    }
+}
+
+/**
+ * Translates a program into a Rust source file defining `name`, a struct
+ * with the same run(), process_code(), add_inputs() and outputs() as
+ * IntCodeComputer, but with every instruction compiled into one arm of a
+ * `match ip` loop.
+ *
+ * Parameters the program writes to through a fixed address are read from
+ * memory, everything else is baked in. Whenever the compiled code can't
+ * be trusted any more, because of a write into the rest of the code, a
+ * jump to an address that wasn't compiled or a negative address, it
+ * hands its state over to an IntCodeComputer and carries on interpreted.
+ * The same goes for arithmetic overflow, so errors are reported exactly
+ * like the interpreter does.
+ * Step limits, tracing and profiling are not supported.
+ *
+ * The generated file refers to this crate as `advent_of_code`.
+ */
+pub fn compile(code: &[i64], name: &str) -> String {
+    let instructions = reachable(code);
+    let written = constant_writes(code, &instructions);
+    let frozen: BTreeSet<usize> = instructions
+        .iter()
+        .flat_map(|(&address, instruction)| address..address + instruction.opcode.size())
+        .filter(|address| !written.contains(address))
+        .collect();
+
+    let mut arms = Vec::new();
+    for (&address, &instruction) in instructions.iter() {
+        if written.contains(&address) {
+            // the opcode itself may change, so there is nothing to compile
+            arms.push(format!(
+                "            {} => return self.interpret(),",
+                address
+            ));
+            continue;
+        }
+        let arm = Arm {
+            code,
+            address,
+            instruction,
+            written: &written,
+        };
+        arms.push(format!(
+            "            {} => {{\n                // {:?}",
+            address, instruction.opcode
+        ));
+        for line in arm.body() {
+            arms.push(format!("                {}", line));
+        }
+        arms.push(String::from("            }"));
+    }
+
+    let program: Vec<String> = code.iter().map(|word| word.to_string()).collect();
+    let frozen = if frozen.is_empty() {
+        String::from("_ if false")
+    } else {
+        ranges(&frozen)
+    };
+
+    TEMPLATE
+        .replace("{name}", name)
+        .replace("{program}", &program.join(", "))
+        .replace("{frozen}", &frozen)
+        .replace("{arms}", &arms.join("\n"))
+}
+
+const TEMPLATE: &str = "// Compiled from an Intcode program by `cargo run compile`, don't edit.
+use advent_of_code::exercises::shared::int_code_computer::{IntCodeComputer, IntCodeError, RunState};
+use advent_of_code::exercises::shared::memory::Memory;
+use advent_of_code::exercises::shared::snapshot::Snapshot;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+const PROGRAM: &[i64] = &[{program}];
+
+/// Turns a value into an address, or hands over to the interpreter so it
+/// can report the negative address.
+macro_rules! address {
+    ($self:ident, $value:expr) => {
+        match usize::try_from($value) {
+            Ok(address) => address,
+            Err(_) => return $self.interpret(),
+        }
+    };
+}
+
+/// Unwraps a checked operation, or hands over to the interpreter so it
+/// can report the overflow.
+macro_rules! checked {
+    ($self:ident, $value:expr) => {
+        match $value {
+            Some(value) => value,
+            None => return $self.interpret(),
+        }
+    };
+}
+
+/// Hands over to the interpreter before the program writes into code
+/// that was compiled.
+macro_rules! writable {
+    ($self:ident, $address:expr) => {{
+        let address = $address;
+        if {name}::is_frozen(address) {
+            return $self.interpret();
+        }
+        address
+    }};
+}
+
+pub struct {name} {
+    memory: Memory,
+    ip: usize,
+    relative_base: i64,
+    steps: u64,
+    is_done: bool,
+    inputs: VecDeque<i64>,
+    output: Vec<i64>,
+    interpreter: Option<IntCodeComputer>,
+}
+
+impl Default for {name} {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(clippy::all, unreachable_code, unused_parens)]
+impl {name} {
+    pub fn new() -> Self {
+        Self {
+            memory: Memory::new(PROGRAM.to_vec()),
+            ip: 0,
+            relative_base: 0,
+            steps: 0,
+            is_done: false,
+            inputs: VecDeque::new(),
+            output: Vec::new(),
+            interpreter: None,
+        }
+    }
+
+    pub fn add_inputs(&mut self, inputs: Vec<i64>) {
+        match &mut self.interpreter {
+            Some(computer) => computer.add_inputs(inputs),
+            None => self.inputs.extend(inputs),
+        }
+    }
+
+    pub fn outputs(&self) -> &[i64] {
+        match &self.interpreter {
+            Some(computer) => computer.outputs(),
+            None => &self.output,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        match &self.interpreter {
+            Some(computer) => computer.is_done,
+            None => self.is_done,
+        }
+    }
+
+    pub fn steps(&self) -> u64 {
+        match &self.interpreter {
+            Some(computer) => computer.steps(),
+            None => self.steps,
+        }
+    }
+
+    /// Whether the compiled code gave up and the interpreter took over.
+    pub fn is_interpreted(&self) -> bool {
+        self.interpreter.is_some()
+    }
+
+    pub fn process_code(&mut self) -> Result<Vec<i64>, IntCodeError> {
+        if let Some(computer) = &mut self.interpreter {
+            return computer.process_code().map(|result| result.output);
+        }
+        self.is_done = false;
+        self.output.clear();
+        while let RunState::Output(_) = self.run()? {}
+        Ok(self.outputs().to_vec())
+    }
+
+    fn is_frozen(address: usize) -> bool {
+        matches!(address, {frozen})
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        // there is no memory limit, so this can't fail
+        self.memory.set(address, value).unwrap();
+    }
+
+    fn interpret(&mut self) -> Result<RunState, IntCodeError> {
+        let snapshot = Snapshot {
+            memory: std::mem::take(&mut self.memory),
+            instruction_pointer: self.ip,
+            relative_base: self.relative_base,
+            steps: self.steps,
+            is_done: self.is_done,
+            program_length: PROGRAM.len(),
+            inputs: self.inputs.drain(..).collect(),
+            output: std::mem::take(&mut self.output),
+        };
+        self.interpreter = Some(IntCodeComputer::from_snapshot(snapshot));
+        self.interpreter.as_mut().unwrap().run()
+    }
+
+    pub fn run(&mut self) -> Result<RunState, IntCodeError> {
+        if let Some(computer) = &mut self.interpreter {
+            return computer.run();
+        }
+        loop {
+            match self.ip {
+{arms}
+            _ => return self.interpret(),
+            }
+        }
+    }
+}
+";
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::*;
+
+    #[test]
+    fn reads_parameters_that_get_written_from_memory() {
+        // the jump target is read as input, much like day 7 does it
+        let code = assemble(
+            "
+                    IN target+2
+            target: JT n, #0
+                    HLT
+            n:      .data 1
+            ",
+        )
+        .unwrap();
+        let source = compile(&code, "Jumper");
+
+        assert!(source.contains("pub struct Jumper {"));
+        assert!(source.contains("let condition = self.memory.get(6);"));
+        assert!(source.contains("let target = self.memory.get(4);"));
+        assert!(source.contains("matches!(address, 0..=3 | 5)"));
+        assert!(source.contains("            5 => {\n                // Halt"));
+    }
+}
@@ -0,0 +1,560 @@
+use super::disassembler::{disassemble, Line};
+use super::instruction::Opcode;
+use super::int_code_computer::{IntCodeComputer, IntCodeError, RunState};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/**
+ * How many instructions can be stepped back over.
+ */
+const HISTORY: usize = 100_000;
+
+/**
+ * The most cells or instructions mem and list show at once.
+ */
+const MAX_COUNT: usize = 1_000;
+
+const HELP: &str = "\
+step [n]             run n instructions (default 1)
+continue             run until a breakpoint, watchpoint, halt or input
+break <addr|OP>      stop before the instruction at addr, or any OP
+delete <addr|OP>     remove a breakpoint
+watch <addr>         stop whenever the cell at addr changes
+unwatch <addr>       remove a watchpoint
+mem <addr> [n]       show n cells of memory (default 8)
+set <addr> <value>   write value to memory
+input <v> [v ...]    queue input values
+info                 show ip, relative base, queues and breakpoints
+list [addr] [n]      disassemble n instructions (default 5) from addr or ip
+back [n]             undo the last n instructions (default 1)
+rcontinue <addr>     go back to before the last write to addr
+quit                 leave the debugger";
+
+/**
+ * Why the debugger handed control back to the user.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(usize),
+    OpcodeBreakpoint(Opcode),
+    Watchpoint { address: usize, old: i64, new: i64 },
+    NeedsInput,
+    Halted,
+    Error(IntCodeError),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(address) => write!(f, "breakpoint at {}", address),
+            Stop::OpcodeBreakpoint(opcode) => write!(f, "breakpoint on {}", opcode.mnemonic()),
+            Stop::Watchpoint { address, old, new } => {
+                write!(f, "watchpoint {}: {} -> {}", address, old, new)
+            }
+            Stop::NeedsInput => write!(f, "waiting for input"),
+            Stop::Halted => write!(f, "halted"),
+            Stop::Error(error) => write!(f, "error: {}", error),
+        }
+    }
+}
+
+pub struct Debugger {
+    computer: IntCodeComputer,
+    breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: Vec<Opcode>,
+    watchpoints: BTreeMap<usize, i64>,
+}
+
+impl Debugger {
+    /**
+     * Starts keeping a history on `computer` unless it already has one.
+     */
+    pub fn new(mut computer: IntCodeComputer) -> Self {
+        if computer.history().is_none() {
+            computer.set_history(Some(HISTORY));
+        }
+        Self {
+            computer,
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: Vec::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn computer(&self) -> &IntCodeComputer {
+        &self.computer
+    }
+
+    /**
+     * Runs up to `steps` instructions, or until something stops it when
+     * `steps` is None. Breakpoints are only checked after the first
+     * instruction so continuing from one doesn't stop right away.
+     */
+    pub fn resume(&mut self, steps: Option<u64>, outputs: &mut Vec<i64>) -> Option<Stop> {
+        let mut taken = 0;
+        loop {
+            if let Some(limit) = steps {
+                if taken >= limit {
+                    return None;
+                }
+            }
+            if taken > 0 {
+                if let Some(stop) = self.breakpoint_hit() {
+                    return Some(stop);
+                }
+            }
+
+            match self.computer.step() {
+                Err(error) => return Some(Stop::Error(error)),
+                Ok(Some(RunState::Halted)) => return Some(Stop::Halted),
+                Ok(Some(RunState::NeedsInput)) => return Some(Stop::NeedsInput),
+                Ok(Some(RunState::Output(value))) => outputs.push(value),
+                Ok(Some(RunState::StepLimitReached)) | Ok(None) => {}
+            }
+            taken += 1;
+
+            if let Some(stop) = self.watchpoint_hit() {
+                return Some(stop);
+            }
+        }
+    }
+
+    fn breakpoint_hit(&self) -> Option<Stop> {
+        let ip = self.computer.instruction_pointer();
+        if self.breakpoints.contains(&ip) {
+            return Some(Stop::Breakpoint(ip));
+        }
+        let opcode = Opcode::from_code(self.computer.peek(ip) % 100)?;
+        if self.opcode_breakpoints.contains(&opcode) {
+            return Some(Stop::OpcodeBreakpoint(opcode));
+        }
+        None
+    }
+
+    fn watchpoint_hit(&mut self) -> Option<Stop> {
+        for (address, old) in self.watchpoints.iter_mut() {
+            let new = self.computer.peek(*address);
+            if new != *old {
+                let stop = Stop::Watchpoint {
+                    address: *address,
+                    old: *old,
+                    new,
+                };
+                *old = new;
+                return Some(stop);
+            }
+        }
+        None
+    }
+
+    /**
+     * Undoes up to `steps` instructions, returning how many there were to
+     * undo. Watchpoints carry on from the values they see now.
+     */
+    pub fn back(&mut self, steps: u64) -> u64 {
+        let mut taken = 0;
+        while taken < steps && self.computer.step_back() {
+            taken += 1;
+        }
+        self.rewatch();
+        taken
+    }
+
+    /**
+     * Goes back to just before the last write to `address`, see
+     * IntCodeComputer::reverse_to_write.
+     */
+    pub fn reverse_to_write(&mut self, address: usize) -> Option<usize> {
+        let ip = self.computer.reverse_to_write(address);
+        self.rewatch();
+        ip
+    }
+
+    fn rewatch(&mut self) {
+        for (address, value) in self.watchpoints.iter_mut() {
+            *value = self.computer.peek(*address);
+        }
+    }
+
+    /**
+     * Disassembles `count` instructions starting at `address`, at most
+     * MAX_COUNT of them.
+     */
+    pub fn list(&self, address: usize, count: usize) -> Vec<Line> {
+        let count = count.min(MAX_COUNT);
+        let window: Vec<i64> = (address..address.saturating_add(count * 4))
+            .map(|address| self.computer.peek(address))
+            .collect();
+        disassemble(&window)
+            .into_iter()
+            .take(count)
+            .map(|line| Line {
+                address: line.address + address,
+                ..line
+            })
+            .collect()
+    }
+
+    fn current_line(&self) -> String {
+        let ip = self.computer.instruction_pointer();
+        format!("=> {}", self.list(ip, 1)[0])
+    }
+
+    fn info(&self) -> String {
+        let breakpoints: Vec<String> = self
+            .breakpoints
+            .iter()
+            .map(|address| address.to_string())
+            .chain(
+                self.opcode_breakpoints
+                    .iter()
+                    .map(|opcode| opcode.mnemonic().to_string()),
+            )
+            .collect();
+        let watchpoints: Vec<String> = self
+            .watchpoints
+            .keys()
+            .map(|address| address.to_string())
+            .collect();
+        format!(
+            "ip: {}\nrelative base: {}\nsteps: {}\ninputs: {:?}\noutputs: {:?}\nbreakpoints: {}\nwatchpoints: {}",
+            self.computer.instruction_pointer(),
+            self.computer.relative_base(),
+            self.computer.steps(),
+            self.computer.pending_inputs(),
+            self.computer.outputs(),
+            breakpoints.join(", "),
+            watchpoints.join(", ")
+        )
+    }
+
+    /**
+     * Runs a single REPL command and returns the text to show for it,
+     * or None when the user asked to quit.
+     */
+    pub fn execute(&mut self, command: &str) -> Option<String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let (name, arguments) = match words.split_first() {
+            Some((name, arguments)) => (*name, arguments),
+            None => return Some(String::new()),
+        };
+        let numbers: Result<Vec<i64>, _> = arguments.iter().map(|word| word.parse()).collect();
+        let numbers = match numbers {
+            Ok(numbers) => numbers,
+            Err(_) if ["break", "b", "delete", "d"].contains(&name) => Vec::new(),
+            Err(_) => return Some(format!("expected numbers, got {:?}", arguments)),
+        };
+        let address = |index: usize| {
+            numbers
+                .get(index)
+                .filter(|value| **value >= 0)
+                .map(|value| *value as usize)
+        };
+        let count = |index: usize, default: usize| match numbers.get(index) {
+            None => Ok(default),
+            Some(&value) if (0..=MAX_COUNT as i64).contains(&value) => Ok(value as usize),
+            Some(value) => Err(format!(
+                "error: count must be between 0 and {}, got {}",
+                MAX_COUNT, value
+            )),
+        };
+
+        let text = match name {
+            "step" | "s" | "continue" | "c" => {
+                let steps = match name {
+                    "step" | "s" => Some(address(0).unwrap_or(1) as u64),
+                    _ => None,
+                };
+                let mut outputs = Vec::new();
+                let stop = self.resume(steps, &mut outputs);
+                let mut lines: Vec<String> = outputs
+                    .iter()
+                    .map(|value| format!("output: {}", value))
+                    .collect();
+                if let Some(stop) = stop {
+                    lines.push(stop.to_string());
+                }
+                lines.push(self.current_line());
+                lines.join("\n")
+            }
+            "break" | "b" | "delete" | "d" => {
+                let adding = name.starts_with('b');
+                match (arguments.first(), address(0)) {
+                    (_, Some(address)) => {
+                        if adding {
+                            self.breakpoints.insert(address);
+                            format!("breakpoint at {}", address)
+                        } else if self.breakpoints.remove(&address) {
+                            format!("deleted breakpoint at {}", address)
+                        } else {
+                            format!("no breakpoint at {}", address)
+                        }
+                    }
+                    (Some(mnemonic), None) => match Opcode::from_mnemonic(mnemonic) {
+                        Some(opcode) => {
+                            let existed = self.opcode_breakpoints.contains(&opcode);
+                            self.opcode_breakpoints
+                                .retain(|existing| *existing != opcode);
+                            if adding {
+                                self.opcode_breakpoints.push(opcode);
+                                format!("breakpoint on {}", opcode.mnemonic())
+                            } else if existed {
+                                format!("deleted breakpoint on {}", opcode.mnemonic())
+                            } else {
+                                format!("no breakpoint on {}", opcode.mnemonic())
+                            }
+                        }
+                        None => format!("unknown opcode {:?}", mnemonic),
+                    },
+                    (None, None) => String::from("usage: break <addr|OP>"),
+                }
+            }
+            "watch" | "w" => match address(0) {
+                Some(address) => {
+                    self.watchpoints
+                        .insert(address, self.computer.peek(address));
+                    format!("watching {}", address)
+                }
+                None => String::from("usage: watch <addr>"),
+            },
+            "unwatch" => match address(0) {
+                Some(address) => {
+                    self.watchpoints.remove(&address);
+                    format!("stopped watching {}", address)
+                }
+                None => String::from("usage: unwatch <addr>"),
+            },
+            "mem" | "x" => match (address(0), count(1, 8)) {
+                (Some(_), Err(error)) => error,
+                (Some(start), Ok(count)) => match start.checked_add(count) {
+                    Some(end) => {
+                        let values: Vec<String> = (start..end)
+                            .map(|address| self.computer.peek(address).to_string())
+                            .collect();
+                        format!("{}: {}", start, values.join(" "))
+                    }
+                    None => format!(
+                        "error: {} cells from {} is past the end of memory",
+                        count, start
+                    ),
+                },
+                (None, _) => String::from("usage: mem <addr> [n]"),
+            },
+            "set" => match (address(0), numbers.get(1)) {
+                (Some(address), Some(value)) => match self.computer.poke(address, *value) {
+                    Ok(()) => {
+                        if let Some(watched) = self.watchpoints.get_mut(&address) {
+                            *watched = *value;
+                        }
+                        format!("{} = {}", address, value)
+                    }
+                    Err(error) => format!("error: {}", error),
+                },
+                _ => String::from("usage: set <addr> <value>"),
+            },
+            "input" | "in" => {
+                self.computer.add_inputs(numbers.clone());
+                format!("inputs: {:?}", self.computer.pending_inputs())
+            }
+            "info" | "i" => self.info(),
+            "list" | "l" => {
+                let start = match (numbers.first(), address(0)) {
+                    (None, _) => Some(self.computer.instruction_pointer()),
+                    (Some(_), start) => start,
+                };
+                match (start, count(1, 5)) {
+                    (Some(start), Ok(count)) => {
+                        let lines: Vec<String> = self
+                            .list(start, count)
+                            .iter()
+                            .map(|line| line.to_string())
+                            .collect();
+                        lines.join("\n")
+                    }
+                    (Some(_), Err(error)) => error,
+                    (None, _) => String::from("usage: list [addr] [n]"),
+                }
+            }
+            "back" | "bs" => {
+                let taken = self.back(address(0).unwrap_or(1) as u64);
+                format!("back {}\n{}", taken, self.current_line())
+            }
+            "rcontinue" | "rc" => match address(0) {
+                Some(address) => match self.reverse_to_write(address) {
+                    Some(_) => format!("last write to {}\n{}", address, self.current_line()),
+                    None => format!("no write to {} in the history", address),
+                },
+                None => String::from("usage: rcontinue <addr>"),
+            },
+            "help" | "h" => String::from(HELP),
+            "quit" | "q" => return None,
+            _ => format!("unknown command {:?}, try help", name),
+        };
+        Some(text)
+    }
+
+    /**
+     * Reads commands until `quit` or the end of input. An empty line
+     * repeats the previous command.
+     */
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", self.current_line())?;
+        write!(output, "(intcode) ")?;
+        output.flush()?;
+
+        let mut previous = String::new();
+        for line in input.lines() {
+            let line = line?;
+            let command = if line.trim().is_empty() {
+                previous.clone()
+            } else {
+                line
+            };
+            match self.execute(&command) {
+                Some(text) => writeln!(output, "{}", text)?,
+                None => break,
+            }
+            previous = command;
+            write!(output, "(intcode) ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble_to_string;
+    use super::*;
+
+    fn debugger() -> Debugger {
+        let message = assemble_to_string(
+            "
+                    IN n
+            loop:   OUT n
+                    ADD n, #-1, n
+                    JT n, #loop
+                    HLT
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        Debugger::new(IntCodeComputer::new(&message).unwrap())
+    }
+
+    #[test]
+    fn stops_for_input_then_at_breakpoints() {
+        let mut debugger = debugger();
+        let mut outputs = Vec::new();
+
+        assert_eq!(debugger.resume(None, &mut outputs), Some(Stop::NeedsInput));
+        debugger.execute("input 3");
+        debugger.execute("break 4");
+        assert_eq!(
+            debugger.resume(None, &mut outputs),
+            Some(Stop::Breakpoint(4))
+        );
+        assert_eq!(outputs, vec![3]);
+
+        assert_eq!(
+            debugger.execute("delete 4"),
+            Some(String::from("deleted breakpoint at 4"))
+        );
+        assert_eq!(
+            debugger.execute("delete 4"),
+            Some(String::from("no breakpoint at 4"))
+        );
+        assert_eq!(
+            debugger.execute("d OUT"),
+            Some(String::from("no breakpoint on OUT"))
+        );
+        debugger.execute("break OUT");
+        assert_eq!(
+            debugger.resume(None, &mut outputs),
+            Some(Stop::OpcodeBreakpoint(Opcode::Output))
+        );
+        assert_eq!(debugger.computer().instruction_pointer(), 2);
+    }
+
+    #[test]
+    fn stops_when_a_watched_cell_changes() {
+        let mut debugger = debugger();
+        let mut outputs = Vec::new();
+        debugger.execute("input 2");
+        debugger.execute("watch 12");
+
+        assert_eq!(
+            debugger.resume(None, &mut outputs),
+            Some(Stop::Watchpoint {
+                address: 12,
+                old: 0,
+                new: 2
+            })
+        );
+        assert_eq!(
+            debugger.resume(None, &mut outputs),
+            Some(Stop::Watchpoint {
+                address: 12,
+                old: 2,
+                new: 1
+            })
+        );
+        assert_eq!(outputs, vec![2]);
+    }
+
+    #[test]
+    fn edits_memory_and_steps() {
+        let mut debugger = debugger();
+        debugger.execute("set 12 5");
+        debugger.execute("set 0 104");
+
+        assert_eq!(debugger.execute("mem 12 1"), Some(String::from("12: 5")));
+        assert!(debugger.execute("list 0 1").unwrap().ends_with("OUT #12"));
+        assert_eq!(
+            debugger.execute("list 0 9223372036854775807"),
+            Some(String::from(
+                "error: count must be between 0 and 1000, got 9223372036854775807"
+            ))
+        );
+        assert!(debugger
+            .execute("mem 9223372036854775807 3")
+            .unwrap()
+            .starts_with("9223372036854775807: 0 0 0"));
+        assert_eq!(
+            debugger.execute("list -1"),
+            Some(String::from("usage: list [addr] [n]"))
+        );
+        assert!(debugger
+            .execute("step 2")
+            .unwrap()
+            .starts_with("output: 12\noutput: 5\n=>"));
+        assert_eq!(debugger.execute("quit"), None);
+    }
+
+    #[test]
+    fn goes_back_to_the_last_write() {
+        let mut debugger = debugger();
+        let mut outputs = Vec::new();
+        debugger.execute("input 3");
+        assert_eq!(debugger.resume(None, &mut outputs), Some(Stop::Halted));
+        assert_eq!(outputs, vec![3, 2, 1]);
+
+        // the ADD that counted down to 0
+        assert!(debugger
+            .execute("rc 12")
+            .unwrap()
+            .ends_with("ADD 12, #-1, 12"));
+        assert_eq!(debugger.computer().peek(12), 1);
+        assert_eq!(debugger.computer().outputs(), &[3, 2, 1]);
+        let text = debugger.execute("back 2").unwrap();
+        assert!(text.starts_with("back 2\n=>") && text.ends_with("JT 12, #2"));
+        assert_eq!(debugger.computer().outputs(), &[3, 2]);
+        assert_eq!(debugger.back(100), 6);
+        assert_eq!(debugger.computer().pending_inputs(), vec![3]);
+        assert_eq!(
+            debugger.execute("rc 12"),
+            Some(String::from("no write to 12 in the history"))
+        );
+    }
+}
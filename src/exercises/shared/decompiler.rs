@@ -0,0 +1,543 @@
+use super::analyzer::{analyze, Analysis, Block, EdgeKind};
+use super::instruction::{Instruction, Mode, Opcode};
+use std::collections::BTreeSet;
+
+enum Line {
+    Label(usize),
+    Code(usize, String),
+}
+
+/**
+ * Where a jump goes: an address, or whatever a parameter holds at run
+ * time.
+ */
+enum Target {
+    Address(usize),
+    Indirect(String),
+}
+
+struct Writer<'a> {
+    code: &'a [i64],
+    analysis: &'a Analysis,
+    lines: Vec<Line>,
+    labels: BTreeSet<usize>,
+    /**
+     * Head and exit of every loop the writer is in, innermost last.
+     */
+    loops: Vec<(usize, usize)>,
+}
+
+impl<'a> Writer<'a> {
+    fn instruction(&self, address: usize) -> Instruction {
+        self.analysis.instructions[&address]
+    }
+
+    fn end(&self, block: &Block) -> usize {
+        let last = block.instructions[block.instructions.len() - 1];
+        last + self.instruction(last).opcode.size()
+    }
+
+    /**
+     * Position parameters name a variable after their address, relative
+     * ones a slot in the current stack frame.
+     */
+    fn operand(&self, address: usize, index: usize) -> String {
+        let value = self.code[address + index + 1];
+        match self.instruction(address).modes[index] {
+            Mode::Position => format!("v{}", value),
+            Mode::Immediate => value.to_string(),
+            Mode::Relative => format!("frame[{}]", value),
+        }
+    }
+
+    /**
+     * The pseudocode for anything but a jump, None for a jump since those
+     * turn into control flow instead.
+     */
+    fn statement(&self, address: usize) -> Option<String> {
+        let operand = |index| self.operand(address, index);
+        let binary = |operator| {
+            format!(
+                "{} = {} {} {};",
+                operand(2),
+                operand(0),
+                operator,
+                operand(1)
+            )
+        };
+        let statement = match self.instruction(address).opcode {
+            Opcode::Add => binary("+"),
+            Opcode::Multiply => binary("*"),
+            Opcode::LessThan => binary("<"),
+            Opcode::Equals => binary("=="),
+            Opcode::Input => format!("{} = input();", operand(0)),
+            Opcode::Output => format!("output({});", operand(0)),
+            Opcode::AdjustRelativeBase => {
+                match (self.instruction(address).modes[0], self.code[address + 1]) {
+                    (Mode::Immediate, size) if size >= 0 => format!("push_frame({});", size),
+                    (Mode::Immediate, size) => format!("pop_frame({});", -size),
+                    _ => format!("frame += {};", operand(0)),
+                }
+            }
+            Opcode::Halt => String::from("halt;"),
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => return None,
+        };
+        Some(statement)
+    }
+
+    /**
+     * The condition under which the jump at `address` is taken, or not
+     * taken if `taken` is false.
+     */
+    fn condition(&self, address: usize, taken: bool) -> String {
+        let jumps_if_true = self.instruction(address).opcode == Opcode::JumpIfTrue;
+        let operator = if jumps_if_true == taken { "!=" } else { "==" };
+        format!("{} {} 0", self.operand(address, 0), operator)
+    }
+
+    /**
+     * Whether the jump at `address` is always (Some(true)) or never taken.
+     */
+    fn constant(&self, address: usize) -> Option<bool> {
+        let instruction = self.instruction(address);
+        match instruction.modes[0] {
+            Mode::Immediate => {
+                Some((self.code[address + 1] != 0) == (instruction.opcode == Opcode::JumpIfTrue))
+            }
+            _ => None,
+        }
+    }
+
+    fn target(&self, address: usize) -> Target {
+        let target = self.code[address + 2];
+        match self.instruction(address).modes[1] {
+            Mode::Immediate if target >= 0 => Target::Address(target as usize),
+            _ => Target::Indirect(self.operand(address, 1)),
+        }
+    }
+
+    fn jump(&mut self, target: Target) -> String {
+        match target {
+            Target::Address(address) => match self.loops.last() {
+                Some(&(head, _)) if head == address => String::from("continue;"),
+                Some(&(_, exit)) if exit == address => String::from("break;"),
+                _ => {
+                    self.labels.insert(address);
+                    format!("goto L{};", address)
+                }
+            },
+            Target::Indirect(operand) => format!("goto *{};", operand),
+        }
+    }
+
+    fn push(&mut self, depth: usize, text: String) {
+        self.lines.push(Line::Code(depth, text));
+    }
+
+    fn statements(&mut self, block: &Block, depth: usize) {
+        for &address in block.instructions.iter() {
+            if let Some(statement) = self.statement(address) {
+                self.push(depth, statement);
+            }
+        }
+    }
+
+    /**
+     * The block jumping back to `head` from furthest down, if that is
+     * still inside the region ending at `to`.
+     */
+    fn latch(&self, head: usize, to: usize) -> Option<&'a Block> {
+        let analysis = self.analysis;
+        analysis
+            .blocks
+            .range(head..to)
+            .rev()
+            .map(|(_, block)| block)
+            .find(|block| {
+                block
+                    .successors
+                    .iter()
+                    .any(|edge| edge.kind == EdgeKind::Jump && edge.target == head)
+            })
+            .filter(|block| self.end(block) <= to && self.reaches(head, block.start))
+    }
+
+    /**
+     * Whether control gets from `from` to `to` without leaving the
+     * addresses in between.
+     */
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        let mut seen = BTreeSet::new();
+        let mut queue = vec![from];
+        while let Some(start) = queue.pop() {
+            if start == to {
+                return true;
+            }
+            if !seen.insert(start) {
+                continue;
+            }
+            if let Some(block) = self.analysis.blocks.get(&start) {
+                queue.extend(
+                    block
+                        .successors
+                        .iter()
+                        .map(|edge| edge.target)
+                        .filter(|&target| target >= from && target <= to),
+                );
+            }
+        }
+        false
+    }
+
+    /**
+     * An unconditional jump with a known target ending the block.
+     */
+    fn goes_to(&self, block: &Block) -> Option<usize> {
+        let last = block.instructions[block.instructions.len() - 1];
+        match self.instruction(last).opcode {
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse if self.constant(last) == Some(true) => {
+                match self.target(last) {
+                    Target::Address(address) => Some(address),
+                    Target::Indirect(_) => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * Writes the blocks starting in `from..to`. A jump to `join` at the
+     * very end is left out, the enclosing if already goes there.
+     */
+    fn region(&mut self, from: usize, to: usize, depth: usize, join: Option<usize>) {
+        let analysis = self.analysis;
+        let mut address = from;
+        let mut falls_in = true;
+        for (_, block) in analysis.blocks.range(from..to) {
+            if block.start < address {
+                continue;
+            }
+            if block.start != address || !falls_in {
+                self.labels.insert(block.start);
+            }
+            // a loop's head is labelled outside of the loop already
+            let in_loop = self.loops.last().map(|&(head, _)| head) == Some(block.start);
+            if !in_loop {
+                self.lines.push(Line::Label(block.start));
+            }
+            address = self.end(block);
+            falls_in = true;
+
+            if let Some(latch) = self.latch(block.start, to).filter(|_| !in_loop) {
+                address = self.write_loop(block, latch, depth);
+                continue;
+            }
+
+            self.statements(block, depth);
+            let last = block.instructions[block.instructions.len() - 1];
+            match self.instruction(last).opcode {
+                Opcode::Halt => falls_in = false,
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => match self.constant(last) {
+                    Some(false) => {}
+                    Some(true) => {
+                        falls_in = false;
+                        let target = self.target(last);
+                        let omitted = match target {
+                            Target::Address(target) => address == to && Some(target) == join,
+                            Target::Indirect(_) => false,
+                        };
+                        if !omitted {
+                            let jump = self.jump(target);
+                            self.push(depth, jump);
+                        }
+                    }
+                    None => match self.target(last) {
+                        Target::Address(target) if target > address && target <= to => {
+                            address = self.write_if(last, address, target, to, depth);
+                        }
+                        target => {
+                            let condition = self.condition(last, true);
+                            let jump = self.jump(target);
+                            self.push(depth, format!("if ({}) {}", condition, jump));
+                        }
+                    },
+                },
+                _ => {}
+            }
+            if falls_in && self.is_written(address) {
+                self.push(
+                    depth,
+                    format!("// runs whatever was written to v{}", address),
+                );
+            }
+        }
+    }
+
+    /**
+     * Whether control runs into a word that the program writes before it
+     * gets there, so there is no telling what instruction it is.
+     */
+    fn is_written(&self, address: usize) -> bool {
+        !self.analysis.instructions.contains_key(&address)
+            && self
+                .analysis
+                .code_writes
+                .iter()
+                .any(|write| write.address == address)
+    }
+
+    /**
+     * Writes the code skipped by a forward jump as the body of an if,
+     * with an else when the body ends by jumping over what follows.
+     * Returns where to carry on.
+     */
+    fn write_if(
+        &mut self,
+        jump: usize,
+        from: usize,
+        target: usize,
+        to: usize,
+        depth: usize,
+    ) -> usize {
+        let analysis = self.analysis;
+        let join = analysis
+            .blocks
+            .range(from..target)
+            .last()
+            .and_then(|(_, block)| self.goes_to(block))
+            .filter(|&join| join > target && join <= to);
+
+        let condition = self.condition(jump, false);
+        self.push(depth, format!("if ({}) {{", condition));
+        self.region(from, target, depth + 1, Some(join.unwrap_or(target)));
+        if let Some(join) = join {
+            self.push(depth, String::from("} else {"));
+            self.region(target, join, depth + 1, Some(join));
+        }
+        self.push(depth, String::from("}"));
+        join.unwrap_or(target)
+    }
+
+    /**
+     * Writes the loop from `head` back up from `latch`, as a while loop if
+     * it tests at the top, a do-while if at the bottom. Returns where the
+     * loop exits to.
+     */
+    fn write_loop(&mut self, head: &Block, latch: &Block, depth: usize) -> usize {
+        let exit = self.end(latch);
+        let last = latch.instructions[latch.instructions.len() - 1];
+        let first = head.instructions[0];
+        let bottom_test = self.constant(last).is_none();
+        let top_test = !bottom_test
+            && head.instructions.len() == 1
+            && head.start != latch.start
+            && matches!(
+                self.instruction(first).opcode,
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse
+            )
+            && self.constant(first).is_none()
+            && matches!(self.target(first), Target::Address(target) if target == exit);
+
+        self.loops.push((head.start, exit));
+        if top_test {
+            let condition = self.condition(first, false);
+            self.push(depth, format!("while ({}) {{", condition));
+            self.region(self.end(head), latch.start, depth + 1, None);
+        } else {
+            self.push(
+                depth,
+                String::from(if bottom_test { "do {" } else { "loop {" }),
+            );
+            self.region(head.start, latch.start, depth + 1, None);
+        }
+        if latch.start != head.start || !top_test {
+            if latch.start != head.start {
+                self.lines.push(Line::Label(latch.start));
+            }
+            self.statements(latch, depth + 1);
+        }
+        self.loops.pop();
+
+        if bottom_test {
+            let condition = self.condition(last, true);
+            self.push(depth, format!("}} while ({});", condition));
+        } else {
+            self.push(depth, String::from("}"));
+        }
+        exit
+    }
+}
+
+/**
+ * Lifts a program into C-like pseudocode. Every memory cell the program
+ * addresses directly becomes a variable named after its address, `v12`
+ * for cell 12, declared with the value it starts out with. Relative
+ * base adjustments are read as setting up a stack frame, so relative
+ * parameters become slots of `frame`.
+ *
+ * Conditional jumps turn into ifs, elses and loops wherever the code is
+ * laid out like that, anything else stays a goto to a label `L` plus the
+ * address. Jumps through memory can only be written as `goto *v12`.
+ */
+pub fn decompile(code: &[i64]) -> String {
+    let analysis = analyze(code);
+    let mut writer = Writer {
+        code,
+        analysis: &analysis,
+        lines: Vec::new(),
+        labels: BTreeSet::new(),
+        loops: Vec::new(),
+    };
+    writer.region(0, code.len(), 0, None);
+
+    let variables: BTreeSet<i64> = analysis
+        .instructions
+        .iter()
+        .flat_map(|(&address, instruction)| {
+            (0..instruction.opcode.parameter_count())
+                .filter(move |&index| instruction.modes[index] == Mode::Position)
+                .map(move |index| code[address + index + 1])
+        })
+        .collect();
+    let mut text = String::new();
+    for variable in variables {
+        let value = match variable {
+            variable if variable >= 0 => code.get(variable as usize).copied().unwrap_or(0),
+            _ => 0,
+        };
+        text.push_str(&format!("var v{} = {};\n", variable, value));
+    }
+    text.push('\n');
+    for line in writer.lines.iter() {
+        match line {
+            Line::Label(address) if writer.labels.contains(address) => {
+                text.push_str(&format!("L{}:\n", address))
+            }
+            Line::Label(_) => {}
+            Line::Code(depth, statement) => {
+                text.push_str(&format!("{}{}\n", "    ".repeat(*depth + 1), statement))
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::super::int_code_computer::read_code;
+    use super::*;
+    use crate::utils;
+
+    fn decompiled(source: &str) -> String {
+        decompile(&assemble(source).unwrap())
+    }
+
+    #[test]
+    fn recovers_if_and_else() {
+        let source = "
+                    IN x
+                    LT x, #10, small
+                    JF small, #big
+                    OUT #1
+                    JT #1, #done
+            big:    OUT #2
+            done:   HLT
+            x:      .data 0
+            small:  .data 0
+        ";
+        assert_eq!(
+            decompiled(source),
+            "var v17 = 0;
+var v18 = 0;
+
+    v17 = input();
+    v18 = v17 < 10;
+    if (v18 != 0) {
+        output(1);
+    } else {
+        output(2);
+    }
+    halt;
+"
+        );
+    }
+
+    #[test]
+    fn recovers_loops_and_stack_frames() {
+        let source = "
+                    ARB #100
+                    IN [0]
+            loop:   JF [0], #end
+                    OUT [0]
+                    ADD [0], #-1, [0]
+                    JT #1, #loop
+            end:    ARB #-100
+            count:  IN n
+            again:  ADD n, #-1, n
+                    JT n, #again
+                    HLT
+            n:      .data 0
+        ";
+        assert_eq!(
+            decompiled(source),
+            "var v28 = 0;
+
+    push_frame(100);
+    frame[0] = input();
+    while (frame[0] != 0) {
+        output(frame[0]);
+        frame[0] = frame[0] + -1;
+    }
+    pop_frame(100);
+    v28 = input();
+    do {
+        v28 = v28 + -1;
+    } while (v28 != 0);
+    halt;
+"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_gotos() {
+        let source = "
+                    IN x
+                    JT #1, #later
+            back:   OUT x
+                    HLT
+            later:  JT x, #back
+                    JT #1, x
+            x:      .data 0
+        ";
+        assert_eq!(
+            decompiled(source),
+            "var v14 = 0;
+
+    v14 = input();
+    goto L8;
+L5:
+    output(v14);
+    halt;
+L8:
+    if (v14 != 0) goto L5;
+    goto *v14;
+"
+        );
+    }
+
+    #[test]
+    fn marks_code_written_at_run_time() {
+        let message = utils::read_file_into_string("./src/exercises/data/data-day5.txt");
+        let text = decompile(&read_code(&message).unwrap());
+        assert!(text.contains(
+            "    v225 = input();
+    v6 = v225 + v6;
+    // runs whatever was written to v6
+L10:
+    output(0);
+"
+        ));
+        assert!(text.lines().filter(|line| line.ends_with(';')).count() > 100);
+    }
+}
@@ -0,0 +1,176 @@
+use super::instruction::{decode, Mode, Opcode};
+use super::int_code_computer::IntCodeComputer;
+use std::fmt;
+
+/**
+ * A parameter as written in the listing:
+ * 5 => position mode
+ * #5 => immediate mode
+ * [5] => relative mode
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Operand {
+    pub mode: Mode,
+    pub value: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LineKind {
+    Instruction {
+        opcode: Opcode,
+        operands: Vec<Operand>,
+    },
+    Data(i64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Line {
+    pub address: usize,
+    pub words: Vec<i64>,
+    pub kind: LineKind,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "{}", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative => write!(f, "[{}]", self.value),
+        }
+    }
+}
+
+impl fmt::Display for LineKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineKind::Instruction { opcode, operands } => {
+                write!(f, "{}", opcode.mnemonic())?;
+                for (index, operand) in operands.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { ", " };
+                    write!(f, "{}{}", separator, operand)?;
+                }
+                Ok(())
+            }
+            LineKind::Data(value) => write!(f, "DATA {}", value),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(|word| word.to_string()).collect();
+        write!(
+            f,
+            "{:>6}  {:<28}{}",
+            self.address,
+            words.join(","),
+            self.kind
+        )
+    }
+}
+
+/**
+ * Decodes a single line starting at `address`, or None if the address is
+ * past the end of the code. Anything that isn't a valid instruction, or
+ * that runs past the end of the code, becomes a DATA word so data regions
+ * and self-modified code never stop the listing.
+ */
+pub fn disassemble_at(code: &[i64], address: usize) -> Option<Line> {
+    let word = *code.get(address)?;
+    if let Ok(instruction) = decode(word, address) {
+        let size = instruction.opcode.size();
+        if address + size <= code.len() {
+            let operands = (0..instruction.opcode.parameter_count())
+                .map(|index| Operand {
+                    mode: instruction.modes[index],
+                    value: code[address + index + 1],
+                })
+                .collect();
+            return Some(Line {
+                address,
+                words: code[address..address + size].to_vec(),
+                kind: LineKind::Instruction {
+                    opcode: instruction.opcode,
+                    operands,
+                },
+            });
+        }
+    }
+
+    Some(Line {
+        address,
+        words: vec![word],
+        kind: LineKind::Data(word),
+    })
+}
+
+pub fn disassemble(code: &[i64]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while let Some(line) = disassemble_at(code, address) {
+        address += line.words.len();
+        lines.push(line);
+    }
+    lines
+}
+
+pub fn listing(code: &[i64]) -> String {
+    disassemble(code)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+impl IntCodeComputer {
+    /**
+     * Disassembles the loaded program as it currently sits in memory, so
+     * any changes the program made to itself show up.
+     */
+    pub fn disassemble(&self) -> Vec<Line> {
+        disassemble(&self.program())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_modes() {
+        let lines = disassemble(&[21101, 5, -1, 7, 204, 3, 1005, 0, 12, 99]);
+        let text: Vec<String> = lines.iter().map(|line| line.kind.to_string()).collect();
+
+        assert_eq!(
+            text,
+            vec!["ADD #5, #-1, [7]", "OUT [3]", "JT 0, #12", "HLT"]
+        );
+        assert_eq!(lines[2].address, 6);
+        assert_eq!(lines[2].words, vec![1005, 0, 12]);
+    }
+
+    #[test]
+    fn falls_back_to_data() {
+        let lines = disassemble(&[104, 7, 99, 0, -1, 1101, 1]);
+        let text: Vec<String> = lines.iter().map(|line| line.kind.to_string()).collect();
+
+        assert_eq!(
+            text,
+            vec!["OUT #7", "HLT", "DATA 0", "DATA -1", "DATA 1101", "DATA 1"]
+        );
+        assert_eq!(disassemble_at(&[104, 7], 2), None);
+    }
+
+    #[test]
+    fn shows_self_modified_code() {
+        // overwrites the 0 at address 6 with a halt before reaching it
+        let mut comp = IntCodeComputer::new("1101,90,9,6,104,1,0").unwrap();
+        comp.process_code().unwrap();
+        let text: Vec<String> = comp
+            .disassemble()
+            .iter()
+            .map(|line| line.kind.to_string())
+            .collect();
+
+        assert_eq!(text, vec!["ADD #90, #9, 6", "OUT #1", "HLT"]);
+    }
+}
@@ -0,0 +1,261 @@
+use std::marker::PhantomData;
+
+/**
+ * One output of a program, together with the step that produced it and
+ * the address of the instruction.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OutputEvent<W = i64> {
+    pub step: u64,
+    pub ip: usize,
+    pub value: W,
+}
+
+pub type OutputCallback<W = i64> = Box<dyn FnMut(&OutputEvent<W>) + Send>;
+
+/**
+ * Identifies a subscriber, see IntCodeComputer::subscribe.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Subscription(usize);
+
+/**
+ * Turns a stream of outputs into values of a more useful type, giving
+ * one whenever enough outputs have come in.
+ */
+pub trait Decoder {
+    type Item;
+
+    fn decode(&mut self, event: &OutputEvent) -> Option<Self::Item>;
+}
+
+/**
+ * Groups every N outputs, like the x, y and tile id a game draws with.
+ * N has to be at least 1, `Tuples::<0>::new()` doesn't compile.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Tuples<const N: usize> {
+    values: [i64; N],
+    filled: usize,
+}
+
+impl<const N: usize> Tuples<N> {
+    const NOT_EMPTY: () = assert!(N > 0, "a tuple needs at least one value");
+
+    pub fn new() -> Self {
+        let () = Self::NOT_EMPTY;
+        Self {
+            values: [0; N],
+            filled: 0,
+        }
+    }
+}
+
+impl<const N: usize> Default for Tuples<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Decoder for Tuples<N> {
+    type Item = [i64; N];
+
+    fn decode(&mut self, event: &OutputEvent) -> Option<[i64; N]> {
+        self.values[self.filled] = event.value;
+        self.filled += 1;
+        if self.filled < N {
+            return None;
+        }
+        self.filled = 0;
+        Some(self.values)
+    }
+}
+
+/**
+ * What Lines makes of the outputs.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Text {
+    /**
+     * A line without its newline.
+     */
+    Line(String),
+    /**
+     * A value outside the ASCII range, usually the answer.
+     */
+    Value(i64),
+}
+
+/**
+ * Frames ASCII output into lines, the way AsciiComputer reads it.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Lines {
+    line: String,
+}
+
+impl Lines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Decoder for Lines {
+    type Item = Text;
+
+    fn decode(&mut self, event: &OutputEvent) -> Option<Text> {
+        match event.value {
+            10 => Some(Text::Line(std::mem::take(&mut self.line))),
+            0..=127 => {
+                self.line.push(event.value as u8 as char);
+                None
+            }
+            value => Some(Text::Value(value)),
+        }
+    }
+}
+
+/**
+ * A decoder made from a closure, which can keep whatever state it needs.
+ */
+pub struct Custom<F, T> {
+    decode: F,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<F, T> Custom<F, T>
+where
+    F: FnMut(&OutputEvent) -> Option<T>,
+{
+    pub fn new(decode: F) -> Self {
+        Self {
+            decode,
+            item: PhantomData,
+        }
+    }
+}
+
+impl<F, T> Decoder for Custom<F, T>
+where
+    F: FnMut(&OutputEvent) -> Option<T>,
+{
+    type Item = T;
+
+    fn decode(&mut self, event: &OutputEvent) -> Option<T> {
+        (self.decode)(event)
+    }
+}
+
+/**
+ * The output subscribers of a computer, called in the order they
+ * subscribed.
+ */
+#[derive(Default)]
+pub struct Subscribers<W = i64> {
+    next: usize,
+    callbacks: Vec<(Subscription, OutputCallback<W>)>,
+}
+
+impl<W> Subscribers<W> {
+    pub fn add(&mut self, callback: OutputCallback<W>) -> Subscription {
+        let subscription = Subscription(self.next);
+        self.next += 1;
+        self.callbacks.push((subscription, callback));
+        subscription
+    }
+
+    pub fn remove(&mut self, subscription: Subscription) -> bool {
+        let count = self.callbacks.len();
+        self.callbacks
+            .retain(|(existing, _)| *existing != subscription);
+        self.callbacks.len() < count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    pub fn publish(&mut self, event: &OutputEvent<W>) {
+        for (_, callback) in self.callbacks.iter_mut() {
+            callback(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::int_code_computer::IntCodeComputer;
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn tags_outputs_across_restarts() {
+        // outputs its input and waits for the next one
+        let mut comp = IntCodeComputer::new("3,7,4,7,1105,1,0,0").unwrap();
+        let (_, events) = comp.stream(Custom::new(|event: &OutputEvent| Some(*event)));
+        comp.add_inputs(vec![7]);
+        comp.process_code().unwrap();
+        comp.add_inputs(vec![8]);
+        assert_eq!(comp.process_code().unwrap().output, vec![8]);
+
+        let events: Vec<OutputEvent> = events.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                OutputEvent {
+                    step: 1,
+                    ip: 2,
+                    value: 7
+                },
+                OutputEvent {
+                    step: 4,
+                    ip: 2,
+                    value: 8
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_tiles_into_triples() {
+        // draws three tiles, then scores 1000
+        let mut comp = IntCodeComputer::new(
+            "104,0,104,0,104,1,104,1,104,0,104,2,104,2,104,0,104,4,104,-1,104,0,104,1000,99",
+        )
+        .unwrap();
+        let tiles = Arc::new(Mutex::new(Vec::new()));
+        let drawn = Arc::clone(&tiles);
+        comp.subscribe_decoded(Tuples::<3>::new(), move |tile| {
+            drawn.lock().unwrap().push(tile)
+        });
+        let (subscription, triples) = comp.stream(Tuples::<3>::new());
+        assert!(comp.unsubscribe(subscription));
+        assert!(!comp.unsubscribe(subscription));
+        comp.process_code().unwrap();
+
+        assert_eq!(
+            *tiles.lock().unwrap(),
+            vec![[0, 0, 1], [1, 0, 2], [2, 0, 4], [-1, 0, 1000]]
+        );
+        assert_eq!(triples.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn frames_ascii_lines() {
+        let mut comp = IntCodeComputer::new(
+            "104,104,104,105,104,10,104,10,104,111,104,107,104,10,104,4261,99",
+        )
+        .unwrap();
+        let (_, text) = comp.stream(Lines::new());
+        comp.process_code().unwrap();
+        assert_eq!(
+            text.try_iter().collect::<Vec<Text>>(),
+            vec![
+                Text::Line(String::from("hi")),
+                Text::Line(String::new()),
+                Text::Line(String::from("ok")),
+                Text::Value(4261)
+            ]
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use super::instruction::{Mode, Opcode};
+use super::int_code_computer::{IntCodeComputer, IntCodeError};
+use super::word::Word;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/**
+ * What happens once an extension's handler is done.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Flow<W = i64> {
+    /**
+     * Carry on with the next instruction.
+     */
+    Continue,
+    Jump(usize),
+    Output(W),
+    /**
+     * Stop without moving on, the instruction runs again once there is
+     * input. Anything the handler wrote to memory stays written.
+     */
+    WaitForInput,
+    Halt,
+}
+
+pub type Handler<W = i64> =
+    Arc<dyn Fn(&mut Context<W>) -> Result<Flow<W>, IntCodeError> + Send + Sync>;
+
+/**
+ * An opcode that isn't part of Intcode, see
+ * IntCodeComputer::register_opcode. Parameters take modes just like the
+ * built-in ones, at most three of them.
+ */
+#[derive(Clone)]
+pub struct Extension<W = i64> {
+    pub code: i64,
+    pub name: String,
+    pub parameters: usize,
+    pub handler: Handler<W>,
+}
+
+impl<W> Extension<W> {
+    pub fn new<F>(code: i64, name: &str, parameters: usize, handler: F) -> Self
+    where
+        F: Fn(&mut Context<W>) -> Result<Flow<W>, IntCodeError> + Send + Sync + 'static,
+    {
+        Self {
+            code,
+            name: name.to_string(),
+            parameters,
+            handler: Arc::new(handler),
+        }
+    }
+}
+
+impl<W> fmt::Debug for Extension<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extension")
+            .field("code", &self.code)
+            .field("name", &self.name)
+            .field("parameters", &self.parameters)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RegisterError {
+    BuiltIn { code: i64, opcode: Opcode },
+    Taken { code: i64, name: String },
+    OutOfRange { code: i64 },
+    TooManyParameters { code: i64, parameters: usize },
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegisterError::BuiltIn { code, opcode } => {
+                write!(f, "opcode {} is already {}", code, opcode.mnemonic())
+            }
+            RegisterError::Taken { code, name } => {
+                write!(f, "opcode {} is already registered as {}", code, name)
+            }
+            RegisterError::OutOfRange { code } => {
+                write!(f, "opcode {} is not between 1 and 98", code)
+            }
+            RegisterError::TooManyParameters { code, parameters } => write!(
+                f,
+                "opcode {} takes {} parameters, at most 3 are possible",
+                code, parameters
+            ),
+        }
+    }
+}
+
+impl Error for RegisterError {}
+
+/**
+ * The registered extensions of a computer by opcode.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Extensions<W = i64> {
+    by_code: HashMap<i64, Extension<W>>,
+}
+
+impl<W> Extensions<W> {
+    pub fn register(&mut self, extension: Extension<W>) -> Result<(), RegisterError> {
+        let code = extension.code;
+        if let Some(opcode) = Opcode::from_code(code) {
+            return Err(RegisterError::BuiltIn { code, opcode });
+        }
+        if !(1..=98).contains(&code) {
+            return Err(RegisterError::OutOfRange { code });
+        }
+        if extension.parameters > 3 {
+            return Err(RegisterError::TooManyParameters {
+                code,
+                parameters: extension.parameters,
+            });
+        }
+        if let Some(existing) = self.by_code.get(&code) {
+            return Err(RegisterError::Taken {
+                code,
+                name: existing.name.clone(),
+            });
+        }
+        self.by_code.insert(code, extension);
+        Ok(())
+    }
+
+    pub fn get(&self, code: i64) -> Option<&Extension<W>> {
+        self.by_code.get(&code)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_code.is_empty()
+    }
+}
+
+/**
+ * What a handler gets to see of the computer: its own parameters, memory
+ * and the next input. The instruction pointer, relative base and outputs
+ * are only changed through the Flow it returns.
+ */
+pub struct Context<'a, W = i64> {
+    computer: &'a mut IntCodeComputer<W>,
+    modes: [Mode; 3],
+    positions: [usize; 3],
+    parameters: usize,
+    input: Option<W>,
+    took_input: bool,
+}
+
+impl<'a, W: Word> Context<'a, W> {
+    pub fn new(
+        computer: &'a mut IntCodeComputer<W>,
+        modes: [Mode; 3],
+        positions: [usize; 3],
+        parameters: usize,
+        input: Option<W>,
+    ) -> Self {
+        Self {
+            computer,
+            modes,
+            positions,
+            parameters,
+            input,
+            took_input: false,
+        }
+    }
+
+    pub fn ip(&self) -> usize {
+        self.computer.instruction_pointer()
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.computer.relative_base()
+    }
+
+    /**
+     * Where parameter `index` points to. Panics past the number of
+     * parameters the extension was registered with.
+     */
+    pub fn address(&self, index: usize) -> usize {
+        assert!(
+            index < self.parameters,
+            "parameter {} of {}",
+            index,
+            self.parameters
+        );
+        self.positions[index]
+    }
+
+    /**
+     * The value of parameter `index`, read according to its mode.
+     */
+    pub fn get(&self, index: usize) -> W {
+        self.computer.peek(self.address(index))
+    }
+
+    /**
+     * Writes through parameter `index`, which can't be in immediate mode.
+     */
+    pub fn set(&mut self, index: usize, value: W) -> Result<(), IntCodeError> {
+        let address = self.address(index);
+        if self.modes[index] == Mode::Immediate {
+            return Err(IntCodeError::WriteInImmediateMode { ip: self.ip() });
+        }
+        self.computer.poke(address, value)
+    }
+
+    pub fn peek(&self, address: usize) -> W {
+        self.computer.peek(address)
+    }
+
+    pub fn poke(&mut self, address: usize, value: W) -> Result<(), IntCodeError> {
+        self.computer.poke(address, value)
+    }
+
+    /**
+     * Takes the next input, None if there is none yet. There is only
+     * one input per instruction.
+     */
+    pub fn input(&mut self) -> Option<W> {
+        let input = self.input.take();
+        self.took_input |= input.is_some();
+        input
+    }
+
+    pub fn took_input(&self) -> bool {
+        self.took_input
+    }
+
+    /**
+     * An error for the handler to return, reported at this instruction.
+     */
+    pub fn fail(&self, message: &str) -> IntCodeError {
+        IntCodeError::Extension {
+            ip: self.ip(),
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn rejects_conflicting_opcodes() {
+        let nop = || Extension::new(42, "NOP", 0, |_| Ok(Flow::Continue));
+        let mut comp = IntCodeComputer::new("99").unwrap();
+        assert_eq!(comp.register_opcode(nop()), Ok(()));
+        assert_eq!(
+            comp.register_opcode(nop()),
+            Err(RegisterError::Taken {
+                code: 42,
+                name: String::from("NOP")
+            })
+        );
+        assert_eq!(
+            comp.register_opcode(Extension::new(4, "PRINT", 1, |_| Ok(Flow::Continue))),
+            Err(RegisterError::BuiltIn {
+                code: 4,
+                opcode: Opcode::Output
+            })
+        );
+        assert_eq!(
+            comp.register_opcode(Extension::new(100, "BIG", 0, |_| Ok(Flow::Continue))),
+            Err(RegisterError::OutOfRange { code: 100 })
+        );
+        assert_eq!(
+            comp.register_opcode(Extension::new(43, "WIDE", 4, |_| Ok(Flow::Continue))),
+            Err(RegisterError::TooManyParameters {
+                code: 43,
+                parameters: 4
+            })
+        );
+    }
+
+    #[test]
+    fn runs_a_debug_print_and_a_host_call() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&printed);
+        // 50 prints its parameter, 51 stores input squared and outputs it
+        let mut comp = IntCodeComputer::new("50,9,150,7,51,10,99,0,0,21").unwrap();
+        comp.register_opcode(Extension::new(50, "DBG", 1, move |context| {
+            log.lock().unwrap().push(context.get(0));
+            Ok(Flow::Continue)
+        }))
+        .unwrap();
+        comp.register_opcode(Extension::new(51, "SQR", 1, |context| {
+            let value = match context.input() {
+                Some(value) => value,
+                None => return Ok(Flow::WaitForInput),
+            };
+            context.set(0, value * value)?;
+            Ok(Flow::Output(value * value))
+        }))
+        .unwrap();
+
+        assert_eq!(comp.process_code().unwrap().output, Vec::<i64>::new());
+        assert_eq!(comp.instruction_pointer(), 4);
+        comp.add_inputs(vec![12]);
+        assert_eq!(comp.process_code().unwrap().output, vec![144]);
+        assert!(comp.is_done);
+        assert_eq!(comp.peek(10), 144);
+        assert_eq!(*printed.lock().unwrap(), vec![21, 7]);
+    }
+}
@@ -0,0 +1,375 @@
+use super::instruction::Opcode;
+use super::int_code_computer::{read_code, IntCodeComputer, IntCodeError};
+use super::limits::Limits;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/**
+ * How far out a case may write, so a random address can't take all the
+ * memory there is.
+ */
+const MEMORY_LIMIT: usize = 1 << 20;
+
+/**
+ * Small and good enough to pick mutations, xorshift64* with a fixed seed
+ * so every run can be repeated.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            // a zero state would only ever produce zeroes
+            state: seed ^ 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /**
+     * A number in 0..bound, bound can't be 0.
+     */
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /**
+     * A word the VM is likely to do something interesting with: mostly
+     * valid instructions and small numbers, now and then something huge.
+     */
+    pub fn word(&mut self, length: usize) -> i64 {
+        match self.below(10) {
+            0..=3 => {
+                let opcode = Opcode::ALL[self.below(Opcode::ALL.len())].code();
+                let modes = (0..3).fold(0, |modes, _| modes * 10 + self.below(3) as i64);
+                modes * 100 + opcode
+            }
+            4..=6 => self.below(length.max(1) + 4) as i64,
+            7 => self.below(21) as i64 - 10,
+            8 => [i64::MIN, i64::MAX, i64::MAX / 2, -1, 1 << 40][self.below(5)],
+            _ => self.next_u64() as i64,
+        }
+    }
+}
+
+/**
+ * A program together with the inputs it gets.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Case {
+    pub code: Vec<i64>,
+    pub inputs: Vec<i64>,
+}
+
+fn join(values: &[i64]) -> String {
+    let words: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    words.join(",")
+}
+
+impl Case {
+    /**
+     * The program on the first line and the inputs on the second, so a
+     * saved case can be run as is.
+     */
+    pub fn to_text(&self) -> String {
+        format!("{}\n{}\n", join(&self.code), join(&self.inputs))
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, IntCodeError> {
+        let mut lines = text.lines();
+        let code = read_code(lines.next().unwrap_or(""))?;
+        let inputs = match lines.next() {
+            Some(line) if !line.trim().is_empty() => read_code(line)?,
+            _ => Vec::new(),
+        };
+        Ok(Self { code, inputs })
+    }
+
+    fn name(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        format!("{:016x}.txt", hasher.finish())
+    }
+}
+
+/**
+ * What running a case came to. Errors are a normal way for a program to
+ * end, only a panic is a bug in the VM.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Finished,
+    Error(IntCodeError),
+    Panic(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Crash {
+    pub case: Case,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub runs: u64,
+    pub coverage: usize,
+    pub corpus: usize,
+    pub crashes: Vec<Crash>,
+}
+
+/**
+ * Runs `case` under `limits` and MEMORY_LIMIT, returning how it went and
+ * which opcodes ran at which addresses.
+ */
+pub fn execute(case: &Case, limits: Limits) -> (Verdict, BTreeSet<(usize, Opcode)>) {
+    let covered = Arc::new(Mutex::new(BTreeSet::new()));
+    let sites = Arc::clone(&covered);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut computer = IntCodeComputer::from_program(case.code.clone());
+        computer.set_memory_limit(Some(MEMORY_LIMIT));
+        computer.set_limits(Some(limits));
+        computer.set_tracer(Some(Box::new(move |event| {
+            sites
+                .lock()
+                .unwrap()
+                .insert((event.ip, event.instruction.opcode));
+        })));
+        computer.add_inputs(case.inputs.clone());
+        computer.process_code()
+    }));
+    let verdict = match result {
+        Ok(Ok(_)) => Verdict::Finished,
+        Ok(Err(error)) => Verdict::Error(error),
+        Err(payload) => Verdict::Panic(
+            payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("panic")),
+        ),
+    };
+    let covered = covered
+        .lock()
+        .map(|sites| sites.clone())
+        .unwrap_or_default();
+    (verdict, covered)
+}
+
+/**
+ * Feeds random and mutated programs to IntCodeComputer, keeping every
+ * case that runs an opcode at an address or ends in an error no earlier
+ * case did, and mutating those further. Cases that make the VM panic are
+ * collected as crashes. With an output directory set, crashes go to
+ * `crashes/` and the kept cases to `corpus/` in it.
+ */
+pub struct Fuzzer {
+    rng: Rng,
+    limits: Limits,
+    corpus: Vec<Case>,
+    coverage: BTreeSet<(usize, Opcode)>,
+    endings: BTreeSet<String>,
+    output: Option<PathBuf>,
+}
+
+impl Fuzzer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            limits: Limits {
+                instructions: Some(10_000),
+                outputs: Some(1_000),
+                ..Limits::default()
+            },
+            corpus: Vec::new(),
+            coverage: BTreeSet::new(),
+            endings: BTreeSet::new(),
+            output: None,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_output(&mut self, directory: Option<PathBuf>) {
+        self.output = directory;
+    }
+
+    /**
+     * Starts mutating from known programs, the puzzle inputs make a good
+     * seed.
+     */
+    pub fn add_seed(&mut self, case: Case) {
+        self.corpus.push(case);
+    }
+
+    pub fn corpus(&self) -> &[Case] {
+        &self.corpus
+    }
+
+    fn generate(&mut self) -> Case {
+        let length = 1 + self.rng.below(32);
+        let code = (0..length).map(|_| self.rng.word(length)).collect();
+        let inputs = (0..self.rng.below(4))
+            .map(|_| self.rng.word(length))
+            .collect();
+        Case { code, inputs }
+    }
+
+    fn mutate(&mut self, case: &Case) -> Case {
+        let mut case = case.clone();
+        for _ in 0..1 + self.rng.below(4) {
+            let length = case.code.len();
+            let address = self.rng.below(length.max(1));
+            let word = self.rng.word(length);
+            match self.rng.below(6) {
+                0 if length > 0 => case.code[address] = word,
+                1 if length > 0 => case.code[address] = case.code[address].wrapping_add(1),
+                // shuffles the parameter modes
+                2 if length > 0 => {
+                    let opcode = case.code[address].rem_euclid(100);
+                    case.code[address] = self.rng.below(1000) as i64 * 100 + opcode;
+                }
+                3 if length > 1 => {
+                    case.code.remove(address);
+                }
+                4 => case.inputs.push(word),
+                _ => case.code.insert(address, word),
+            }
+        }
+        case
+    }
+
+    fn save(&self, kind: &str, case: &Case) -> io::Result<()> {
+        if let Some(directory) = &self.output {
+            let directory = directory.join(kind);
+            fs::create_dir_all(&directory)?;
+            fs::write(directory.join(case.name()), case.to_text())?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Runs `iterations` cases, half of them fresh, half mutated from the
+     * corpus once there is one.
+     */
+    pub fn run(&mut self, iterations: u64) -> io::Result<Report> {
+        let mut report = Report::default();
+        for _ in 0..iterations {
+            let case = if self.corpus.is_empty() || self.rng.below(2) == 0 {
+                self.generate()
+            } else {
+                let parent = self.corpus[self.rng.below(self.corpus.len())].clone();
+                self.mutate(&parent)
+            };
+            let (verdict, covered) = execute(&case, self.limits);
+            report.runs += 1;
+
+            let ending = match &verdict {
+                Verdict::Finished => String::from("finished"),
+                Verdict::Error(error) => format!("{:?}", error)
+                    .split(|c: char| !c.is_alphanumeric())
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                Verdict::Panic(message) => {
+                    self.save("crashes", &case)?;
+                    report.crashes.push(Crash {
+                        case: case.clone(),
+                        message: message.clone(),
+                    });
+                    String::from("panic")
+                }
+            };
+            let new_sites = covered.difference(&self.coverage).count();
+            let new_ending = self.endings.insert(ending);
+            if new_sites > 0 || new_ending {
+                self.coverage.extend(covered);
+                self.save("corpus", &case)?;
+                self.corpus.push(case);
+            }
+        }
+        report.coverage = self.coverage.len();
+        report.corpus = self.corpus.len();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_verdicts_not_panics() {
+        let limits = Limits {
+            instructions: Some(100),
+            ..Limits::default()
+        };
+        let cases = [
+            ("1,0,0,0,99", Verdict::Finished),
+            (
+                "3,-1,99",
+                Verdict::Error(IntCodeError::NegativeAddress { ip: 0, address: -1 }),
+            ),
+            (
+                "1501,0,0,0",
+                Verdict::Error(IntCodeError::InvalidMode { ip: 0, mode: 5 }),
+            ),
+            (
+                "109,-5,2201,0,0,0",
+                Verdict::Error(IntCodeError::NegativeAddress { ip: 2, address: -5 }),
+            ),
+            (
+                "1105,1,9223372036854775807",
+                Verdict::Error(IntCodeError::InvalidOpcode {
+                    ip: 9223372036854775807,
+                    opcode: 0,
+                }),
+            ),
+        ];
+        for (code, expected) in cases.iter() {
+            let case = Case {
+                code: read_code(code).unwrap(),
+                inputs: Vec::new(),
+            };
+            assert_eq!(execute(&case, limits).0, *expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn finds_no_crashes_and_grows_the_corpus() {
+        let mut fuzzer = Fuzzer::new(2019);
+        fuzzer.add_seed(Case {
+            code: read_code("3,9,8,9,10,9,4,9,99,-1,8").unwrap(),
+            inputs: vec![8],
+        });
+        let report = fuzzer.run(2_000).unwrap();
+        assert_eq!(report.runs, 2_000);
+        assert_eq!(report.crashes, vec![]);
+        assert!(report.corpus > 1);
+        assert!(report.coverage > 20);
+    }
+
+    #[test]
+    fn saved_cases_read_back() {
+        let case = Case {
+            code: vec![3, 0, 4, 0, 99],
+            inputs: vec![-7],
+        };
+        assert_eq!(Case::from_text(&case.to_text()), Ok(case));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+
+/**
+ * Everything one instruction changed, enough to put the computer back to
+ * how it was before it ran.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Change<W = i64> {
+    pub step: u64,
+    pub ip: usize,
+    pub relative_base: i64,
+    pub was_done: bool,
+    /**
+     * Written addresses with the value they held before, in the order of
+     * the writes.
+     */
+    pub writes: Vec<(usize, W)>,
+    pub input: Option<W>,
+    /**
+     * How many outputs there were before.
+     */
+    pub outputs: usize,
+}
+
+/**
+ * An undo log of the last `capacity` instructions, see
+ * IntCodeComputer::set_history.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct History<W = i64> {
+    changes: VecDeque<Change<W>>,
+    capacity: usize,
+    current: Option<Change<W>>,
+}
+
+impl<W> History<W> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            changes: VecDeque::new(),
+            capacity,
+            current: None,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn changes(&self) -> impl Iterator<Item = &Change<W>> {
+        self.changes.iter()
+    }
+
+    /**
+     * Starts recording the changes of the instruction about to run.
+     */
+    pub fn begin(&mut self, change: Change<W>) {
+        self.current = Some(change);
+    }
+
+    pub fn record_write(&mut self, address: usize, old: W) {
+        if let Some(change) = &mut self.current {
+            change.writes.push((address, old));
+        }
+    }
+
+    pub fn record_input(&mut self, value: W) {
+        if let Some(change) = &mut self.current {
+            change.input = Some(value);
+        }
+    }
+
+    /**
+     * Ends the instruction, keeping its change if it made one: it ran to
+     * the end, halted or got as far as writing or reading input before
+     * failing. The oldest change is forgotten once there are too many.
+     */
+    pub fn finish(&mut self, steps: u64, is_done: bool) {
+        if let Some(change) = self.current.take() {
+            let changed = change.step != steps
+                || change.was_done != is_done
+                || !change.writes.is_empty()
+                || change.input.is_some();
+            if self.capacity == 0 || !changed {
+                return;
+            }
+            if self.changes.len() == self.capacity {
+                self.changes.pop_front();
+            }
+            self.changes.push_back(change);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Change<W>> {
+        self.changes.pop_back()
+    }
+
+    /**
+     * How many instructions back the last write to `address` is, 1 for the
+     * instruction that just ran.
+     */
+    pub fn last_write(&self, address: usize) -> Option<usize> {
+        self.changes
+            .iter()
+            .rev()
+            .position(|change| change.writes.iter().any(|&(written, _)| written == address))
+            .map(|index| index + 1)
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+        self.current = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble_to_string;
+    use super::super::int_code_computer::IntCodeComputer;
+
+    // sums its inputs on a stack until it reads a 0
+    const SUM: &str = "
+                ARB #100
+        read:   IN [0]
+                ADD [0], total, total
+                OUT total
+                ARB #1
+                JT [-1], #read
+                HLT
+        total:  .data 0
+    ";
+
+    #[test]
+    fn steps_back_over_everything() {
+        let mut comp = IntCodeComputer::new(&assemble_to_string(SUM).unwrap()).unwrap();
+        comp.set_history(Some(100));
+        comp.add_inputs(vec![3, 4, 0]);
+        let before = comp.snapshot();
+        assert_eq!(comp.process_code().unwrap().output, vec![3, 7, 7]);
+        let finished = comp.snapshot();
+
+        let mut undone = 0;
+        while comp.step_back() {
+            undone += 1;
+        }
+        assert_eq!(undone, 17);
+        // memory stays grown, but holds what it did before
+        let mut rewound = comp.snapshot();
+        assert!((100..103).all(|address| rewound.memory.get(address) == 0));
+        rewound.memory = before.memory.clone();
+        assert_eq!(rewound, before);
+
+        assert_eq!(comp.process_code().unwrap().output, vec![3, 7, 7]);
+        assert_eq!(comp.snapshot(), finished);
+    }
+
+    #[test]
+    fn keeps_a_bounded_history() {
+        let mut comp = IntCodeComputer::new(&assemble_to_string(SUM).unwrap()).unwrap();
+        comp.set_history(Some(6));
+        comp.add_inputs(vec![3, 4, 0]);
+        comp.process_code().unwrap();
+        assert_eq!(comp.history().map(|history| history.len()), Some(6));
+        assert!(comp.step_back());
+        assert_eq!(comp.instruction_pointer(), 15);
+        assert_eq!(comp.relative_base(), 103);
+        assert!(!comp.is_done);
+
+        // back to the last ADD, before it wrote total
+        assert_eq!(comp.reverse_to_write(16), Some(4));
+        assert_eq!(comp.instruction_pointer(), 4);
+        assert_eq!(comp.relative_base(), 102);
+        assert_eq!(comp.peek(16), 7);
+        assert_eq!(comp.outputs(), &[3, 7]);
+        // the ADD before that has been forgotten
+        assert_eq!(comp.reverse_to_write(16), None);
+        assert_eq!(comp.instruction_pointer(), 4);
+        assert!(comp.step_back());
+        assert_eq!(comp.pending_inputs(), vec![0]);
+        assert!(!comp.step_back());
+    }
+}
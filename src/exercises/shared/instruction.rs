@@ -0,0 +1,340 @@
+use super::int_code_computer::IntCodeError;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Opcode {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Halt,
+}
+
+/**
+ * 0 => position mode
+ * 1 => immediate mode
+ * 2 => relative mode
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+/**
+ * The decoded form of the first word of an instruction. Modes past the
+ * opcode's parameter count are always Position.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub modes: [Mode; 3],
+}
+
+impl Opcode {
+    pub const ALL: [Opcode; 10] = [
+        Opcode::Add,
+        Opcode::Multiply,
+        Opcode::Input,
+        Opcode::Output,
+        Opcode::JumpIfTrue,
+        Opcode::JumpIfFalse,
+        Opcode::LessThan,
+        Opcode::Equals,
+        Opcode::AdjustRelativeBase,
+        Opcode::Halt,
+    ];
+
+    pub fn from_code(code: i64) -> Option<Opcode> {
+        Opcode::ALL
+            .iter()
+            .copied()
+            .find(|opcode| opcode.code() == code)
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        Opcode::ALL
+            .iter()
+            .copied()
+            .find(|opcode| opcode.mnemonic().eq_ignore_ascii_case(mnemonic))
+    }
+
+    pub fn code(self) -> i64 {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Multiply => 2,
+            Opcode::Input => 3,
+            Opcode::Output => 4,
+            Opcode::JumpIfTrue => 5,
+            Opcode::JumpIfFalse => 6,
+            Opcode::LessThan => 7,
+            Opcode::Equals => 8,
+            Opcode::AdjustRelativeBase => 9,
+            Opcode::Halt => 99,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Multiply => "MUL",
+            Opcode::Input => "IN",
+            Opcode::Output => "OUT",
+            Opcode::JumpIfTrue => "JT",
+            Opcode::JumpIfFalse => "JF",
+            Opcode::LessThan => "LT",
+            Opcode::Equals => "EQ",
+            Opcode::AdjustRelativeBase => "ARB",
+            Opcode::Halt => "HLT",
+        }
+    }
+
+    pub fn parameter_count(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => 3,
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => 2,
+            Opcode::Input | Opcode::Output | Opcode::AdjustRelativeBase => 1,
+            Opcode::Halt => 0,
+        }
+    }
+
+    /**
+     * Whether the last parameter is an address that gets written to.
+     */
+    pub fn writes(self) -> bool {
+        matches!(
+            self,
+            Opcode::Add | Opcode::Multiply | Opcode::Input | Opcode::LessThan | Opcode::Equals
+        )
+    }
+
+    /**
+     * Number of words the instruction takes up, opcode included.
+     */
+    pub fn size(self) -> usize {
+        self.parameter_count() + 1
+    }
+}
+
+impl Mode {
+    pub fn from_code(code: i64) -> Option<Mode> {
+        match code {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Splits the word at `ip` into its opcode and parameter modes, checking
+ * that only modes that are actually used are valid and that nothing is
+ * written through an immediate parameter.
+ */
+pub fn decode(word: i64, ip: usize) -> Result<Instruction, IntCodeError> {
+    let opcode =
+        Opcode::from_code(word % 100).ok_or(IntCodeError::InvalidOpcode { ip, opcode: word })?;
+    let modes = decode_modes(word, ip, opcode.parameter_count())?;
+
+    if opcode.writes() && modes[opcode.parameter_count() - 1] == Mode::Immediate {
+        return Err(IntCodeError::WriteInImmediateMode { ip });
+    }
+
+    Ok(Instruction { opcode, modes })
+}
+
+/**
+ * The modes of the first `count` parameters of the instruction `word`,
+ * the rest are left as Position.
+ */
+pub fn decode_modes(word: i64, ip: usize, count: usize) -> Result<[Mode; 3], IntCodeError> {
+    let mut modes = [Mode::Position; 3];
+    let mut divisor = 100;
+    for mode in modes.iter_mut().take(count) {
+        let code = word / divisor % 10;
+        *mode = Mode::from_code(code).ok_or(IntCodeError::InvalidMode { ip, mode: code })?;
+        divisor *= 10;
+    }
+    Ok(modes)
+}
+
+/**
+ * Where a parameter of a decoded instruction points to, worked out as far
+ * as it can be before the instruction runs.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operand {
+    /** Position and immediate mode, the address never changes. */
+    Address(usize),
+    /** Relative mode, an offset from the relative base at the time it runs. */
+    Relative(i64),
+    /** Position mode below 0, an error once the instruction runs. */
+    Negative(i64),
+}
+
+/**
+ * An instruction together with its operands, ready to run without looking
+ * at its words again.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Decoded {
+    pub instruction: Instruction,
+    pub operands: [Operand; 3],
+}
+
+/**
+ * Instructions are only cached this far into memory, code doesn't run
+ * from anywhere further out in practice.
+ */
+const CACHE_LIMIT: usize = 1 << 16;
+
+/**
+ * The cache is split into pages of this many addresses, so a clone only
+ * has to copy the pages it writes to.
+ */
+const PAGE_SIZE: usize = 64;
+
+/**
+ * Decoded instructions by address. A write anywhere inside a cached
+ * instruction has to be reported through invalidate(), otherwise
+ * self-modifying code would keep running the old version. Clones share
+ * their pages until one of them changes.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeCache {
+    pages: Vec<Arc<Vec<Option<Decoded>>>>,
+}
+
+impl DecodeCache {
+    #[inline]
+    pub fn get(&self, address: usize) -> Option<Decoded> {
+        match self.pages.get(address / PAGE_SIZE) {
+            Some(page) => page[address % PAGE_SIZE],
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, address: usize, decoded: Decoded) {
+        if address >= CACHE_LIMIT {
+            return;
+        }
+        let page = address / PAGE_SIZE;
+        if page >= self.pages.len() {
+            self.pages
+                .resize_with(page + 1, || Arc::new(vec![None; PAGE_SIZE]));
+        }
+        Arc::make_mut(&mut self.pages[page])[address % PAGE_SIZE] = Some(decoded);
+    }
+
+    /**
+     * Forgets every instruction that covers `address`. Instructions are at
+     * most four words long, so only the three addresses before it can
+     * start one.
+     */
+    #[inline]
+    pub fn invalidate(&mut self, address: usize) {
+        for start in address.saturating_sub(3)..=address {
+            if let Some(page) = self.pages.get_mut(start / PAGE_SIZE) {
+                let covers = match page[start % PAGE_SIZE] {
+                    Some(decoded) => {
+                        start + decoded.instruction.opcode.parameter_count() >= address
+                    }
+                    None => false,
+                };
+                if covers {
+                    Arc::make_mut(page)[start % PAGE_SIZE] = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_opcode_and_modes() {
+        assert_eq!(
+            decode(1002, 0),
+            Ok(Instruction {
+                opcode: Opcode::Multiply,
+                modes: [Mode::Position, Mode::Immediate, Mode::Position],
+            })
+        );
+        assert_eq!(
+            decode(21107, 0),
+            Ok(Instruction {
+                opcode: Opcode::LessThan,
+                modes: [Mode::Immediate, Mode::Immediate, Mode::Relative],
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_modes_past_the_parameters() {
+        assert_eq!(
+            decode(30099, 4),
+            Ok(Instruction {
+                opcode: Opcode::Halt,
+                modes: [Mode::Position; 3],
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_words() {
+        assert_eq!(
+            decode(42, 3),
+            Err(IntCodeError::InvalidOpcode { ip: 3, opcode: 42 })
+        );
+        assert_eq!(
+            decode(304, 3),
+            Err(IntCodeError::InvalidMode { ip: 3, mode: 3 })
+        );
+        assert_eq!(
+            decode(1103, 3),
+            Err(IntCodeError::WriteInImmediateMode { ip: 3 })
+        );
+    }
+
+    #[test]
+    fn invalidates_every_instruction_covering_a_write() {
+        let decoded = Decoded {
+            instruction: decode(1, 0).unwrap(),
+            operands: [Operand::Address(0); 3],
+        };
+        let mut cache = DecodeCache::default();
+        for address in 0..8 {
+            cache.insert(address, decoded);
+        }
+        let shared = cache.clone();
+
+        cache.invalidate(5);
+        let cached: Vec<bool> = (0..8).map(|address| cache.get(address).is_some()).collect();
+        assert_eq!(
+            cached,
+            vec![true, true, false, false, false, false, true, true]
+        );
+        cache.invalidate(1_000_000);
+        assert_eq!(cache.get(1_000_000), None);
+        // clones share their entries, but not what gets forgotten
+        assert_eq!(shared.get(5), Some(decoded));
+
+        // a halt at 4 is a single word, so a write to 5 leaves it alone
+        let halt = Decoded {
+            instruction: decode(99, 4).unwrap(),
+            operands: [Operand::Address(0); 3],
+        };
+        cache.insert(4, halt);
+        cache.invalidate(5);
+        assert_eq!(cache.get(4), Some(halt));
+    }
+}
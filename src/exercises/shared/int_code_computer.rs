@@ -1,269 +1,1215 @@
-pub struct IntCodeComputer {
-    code: Vec<i64>,
-    inputs: Vec<i64>,
+use super::events::{Decoder, OutputCallback, OutputEvent, Subscribers, Subscription};
+use super::extension::{Context, Extension, Extensions, Flow, Handler, RegisterError};
+use super::history::{Change, History};
+use super::instruction::{decode, decode_modes, DecodeCache, Decoded, Mode, Opcode, Operand};
+use super::intcode_io::IntcodeIo;
+use super::limits::{Limit, Limits, Watchdog};
+use super::memory::Memory;
+use super::snapshot::Snapshot;
+use super::trace::{Profile, TraceCallback, TraceEvent};
+use super::word::Word;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+/**
+ * Runs Intcode on i64 words, or on any other Word for programs that
+ * outgrow them, see word::BigComputer.
+ */
+pub struct IntCodeComputer<W = i64> {
+    code: Memory<W>,
+    inputs: VecDeque<W>,
     instruction_pointer: usize,
     pub is_done: bool,
-    output: Vec<i64>,
+    output: Vec<W>,
     relative_base: i64,
+    step_limit: Option<u64>,
+    steps: u64,
+    program_length: usize,
+    /**
+     * The program as it was loaded, shared between the computers
+     * load_patched makes from this one.
+     */
+    image: Arc<Vec<W>>,
+    tracer: Option<TraceCallback<W>>,
+    subscribers: Subscribers<W>,
+    profile: Option<Profile>,
+    decode_cache: Option<DecodeCache>,
+    watchdog: Option<Watchdog>,
+    extensions: Extensions<W>,
+    history: Option<History<W>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct IntCodeComputerResult {
-    pub code: Vec<i64>,
-    pub output: Vec<i64>,
+pub struct IntCodeComputerResult<W = i64> {
+    pub code: Vec<W>,
+    pub output: Vec<W>,
+}
+
+/**
+ * Why a call to run() stopped.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RunState<T = i64> {
+    Halted,
+    NeedsInput,
+    Output(T),
+    StepLimitReached,
+}
+
+/**
+ * Everything that can go wrong while loading or running a program.
+ * Each variant carries the instruction pointer of the failing instruction,
+ * or the position of the offending value when parsing.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntCodeError {
+    InvalidOpcode {
+        ip: usize,
+        opcode: i64,
+    },
+    InvalidMode {
+        ip: usize,
+        mode: i64,
+    },
+    NegativeAddress {
+        ip: usize,
+        address: i64,
+    },
+    WriteInImmediateMode {
+        ip: usize,
+    },
+    InputExhausted {
+        ip: usize,
+    },
+    /**
+     * A write went past set_memory_limit(). The snapshot is the state
+     * right before the write, like the one of LimitReached.
+     */
+    MemoryLimitExceeded {
+        ip: usize,
+        address: usize,
+        limit: usize,
+        snapshot: Option<Box<Snapshot>>,
+    },
+    Overflow {
+        ip: usize,
+    },
+    /**
+     * One of the limits given to set_limits() was hit. The snapshot is
+     * the state right before the instruction that would have gone over,
+     * None if the computer works on a wider Word and some value there
+     * doesn't fit into an i64.
+     */
+    LimitReached {
+        ip: usize,
+        limit: Limit,
+        snapshot: Option<Box<Snapshot>>,
+    },
+    /**
+     * Raised by the handler of a registered opcode.
+     */
+    Extension {
+        ip: usize,
+        message: String,
+    },
+    Parse {
+        ip: usize,
+        value: String,
+    },
 }
 
-pub fn read_code(message: &str) -> Vec<i64> {
-    let mut code: Vec<i64> = vec![0; 100000];
-    for (index, val) in message.trim().split(",").enumerate() {
-        let val = val.parse::<i64>().unwrap();
-        code.insert(index, val);
+impl IntCodeError {
+    pub fn ip(&self) -> usize {
+        match *self {
+            IntCodeError::InvalidOpcode { ip, .. }
+            | IntCodeError::InvalidMode { ip, .. }
+            | IntCodeError::NegativeAddress { ip, .. }
+            | IntCodeError::WriteInImmediateMode { ip }
+            | IntCodeError::InputExhausted { ip }
+            | IntCodeError::MemoryLimitExceeded { ip, .. }
+            | IntCodeError::Overflow { ip }
+            | IntCodeError::LimitReached { ip, .. }
+            | IntCodeError::Extension { ip, .. }
+            | IntCodeError::Parse { ip, .. } => ip,
+        }
     }
-    code
+}
+
+impl fmt::Display for IntCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntCodeError::InvalidOpcode { ip, opcode } => {
+                write!(f, "invalid opcode {} at {}", opcode, ip)
+            }
+            IntCodeError::InvalidMode { ip, mode } => {
+                write!(f, "invalid parameter mode {} at {}", mode, ip)
+            }
+            IntCodeError::NegativeAddress { ip, address } => {
+                write!(f, "negative address {} at {}", address, ip)
+            }
+            IntCodeError::WriteInImmediateMode { ip } => {
+                write!(f, "write to an immediate mode parameter at {}", ip)
+            }
+            IntCodeError::InputExhausted { ip } => write!(f, "no input left at {}", ip),
+            IntCodeError::MemoryLimitExceeded {
+                ip, address, limit, ..
+            } => write!(
+                f,
+                "write to {} exceeds the memory limit of {} at {}",
+                address, limit, ip
+            ),
+            IntCodeError::Overflow { ip } => write!(f, "arithmetic overflow at {}", ip),
+            IntCodeError::LimitReached { ip, limit, .. } => {
+                write!(f, "stopped by the {} at {}", limit, ip)
+            }
+            IntCodeError::Extension { ip, message } => write!(f, "{} at {}", message, ip),
+            IntCodeError::Parse { ip, value } => {
+                write!(f, "could not parse {:?} at position {}", value, ip)
+            }
+        }
+    }
+}
+
+impl Error for IntCodeError {}
+
+pub fn read_code(message: &str) -> Result<Vec<i64>, IntCodeError> {
+    read_words(message)
+}
+
+/**
+ * Like read_code, for a computer on a wider Word.
+ */
+pub fn read_words<W: Word>(message: &str) -> Result<Vec<W>, IntCodeError> {
+    message
+        .trim()
+        .split(',')
+        .enumerate()
+        .map(|(index, val)| {
+            val.trim().parse::<W>().map_err(|_| IntCodeError::Parse {
+                ip: index,
+                value: val.to_string(),
+            })
+        })
+        .collect()
 }
 
 impl IntCodeComputer {
-    pub fn new(message: &str) -> Self {
-        Self {
-            code: read_code(message),
-            inputs: Vec::new(),
+    pub fn new(message: &str) -> Result<Self, IntCodeError> {
+        Ok(Self::from_program(read_code(message)?))
+    }
+
+    /**
+     * Loads an already parsed program.
+     */
+    pub fn from_program(program: Vec<i64>) -> Self {
+        Self::from_words(program)
+    }
+
+    /**
+     * Like run(), but inputs are pulled from `io` whenever the program
+     * asks for one and every output is handed to it. Returns once the
+     * program halts, the step limit is used up or `io` has no input ready.
+     */
+    pub fn run_io<T: IntcodeIo + ?Sized>(&mut self, io: &mut T) -> Result<RunState, IntCodeError> {
+        loop {
+            match self.run()? {
+                RunState::Output(value) => io.output(value),
+                RunState::NeedsInput => match io.input() {
+                    Some(value) => self.inputs.push_back(value),
+                    None => return Ok(RunState::NeedsInput),
+                },
+                state => return Ok(state),
+            }
+        }
+    }
+
+    /**
+     * Calls `handler` with whatever `decoder` makes of the outputs.
+     */
+    pub fn subscribe_decoded<D, F>(&mut self, mut decoder: D, mut handler: F) -> Subscription
+    where
+        D: Decoder + Send + 'static,
+        F: FnMut(D::Item) + Send + 'static,
+    {
+        self.subscribe(Box::new(move |event| {
+            if let Some(item) = decoder.decode(event) {
+                handler(item);
+            }
+        }))
+    }
+
+    /**
+     * Sends whatever `decoder` makes of the outputs down a channel, to be
+     * read at leisure or from another thread.
+     */
+    pub fn stream<D>(&mut self, decoder: D) -> (Subscription, Receiver<D::Item>)
+    where
+        D: Decoder + Send + 'static,
+        D::Item: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let subscription = self.subscribe_decoded(decoder, move |item| {
+            // nobody listening anymore is fine
+            let _ = sender.send(item);
+        });
+        (subscription, receiver)
+    }
+}
+
+impl<W: Word> IntCodeComputer<W> {
+    /**
+     * Like new(), for a computer on a wider Word, e.g.
+     * `BigComputer::parse(message)`.
+     */
+    pub fn parse(message: &str) -> Result<Self, IntCodeError> {
+        Ok(Self::from_words(read_words(message)?))
+    }
+
+    pub fn from_words(program: Vec<W>) -> Self {
+        Self::from_image(Arc::new(program))
+    }
+
+    fn from_image(image: Arc<Vec<W>>) -> Self {
+        let mut computer = Self {
+            program_length: image.len(),
+            code: Memory::new(image.to_vec()),
+            image,
+            inputs: VecDeque::new(),
             instruction_pointer: 0,
             is_done: false,
             output: Vec::new(),
             relative_base: 0,
+            step_limit: None,
+            steps: 0,
+            tracer: None,
+            subscribers: Subscribers::default(),
+            profile: None,
+            decode_cache: None,
+            watchdog: None,
+            extensions: Extensions::default(),
+            history: None,
+        };
+        computer.set_decode_cache(true);
+        computer
+    }
+
+    /**
+     * A fresh computer running the program this one was loaded with, with
+     * `patches` written over it. The loaded program isn't parsed again
+     * and stays shared for reset(), only the working memory is copied
+     * from it. Takes on the step limit, limits, registered opcodes,
+     * memory limit, decode cache and history settings of this one.
+     */
+    pub fn load_patched(&self, patches: &[(usize, W)]) -> Result<Self, IntCodeError> {
+        let mut computer = Self::from_image(Arc::clone(&self.image));
+        computer.code.set_limit(self.code.limit());
+        if self.decode_cache.is_none() {
+            computer.set_decode_cache(false);
+        }
+        computer.step_limit = self.step_limit;
+        computer.set_limits(self.limits());
+        computer.extensions = self.extensions.clone();
+        computer.set_history(self.history.as_ref().map(History::capacity));
+        computer.patch(patches)?;
+        Ok(computer)
+    }
+
+    /**
+     * Goes back to the program as it was loaded, with no inputs, outputs
+     * or history and the limits counting from zero again. Settings like
+     * the tracer, registered opcodes and the profile so far stay.
+     */
+    pub fn reset(&mut self) {
+        let limit = self.code.limit();
+        self.code = Memory::new(self.image.to_vec());
+        self.code.set_limit(limit);
+        self.program_length = self.image.len();
+        self.inputs.clear();
+        self.instruction_pointer = 0;
+        self.is_done = false;
+        self.output.clear();
+        self.relative_base = 0;
+        self.steps = 0;
+        self.set_decode_cache(self.decode_cache.is_some());
+        self.set_limits(self.limits());
+        if let Some(history) = &mut self.history {
+            history.clear();
         }
     }
 
-    pub fn process_code(&mut self) -> IntCodeComputerResult {
+    /**
+     * Runs until the program halts or waits for input, collecting every
+     * output produced along the way.
+     */
+    pub fn process_code(&mut self) -> Result<IntCodeComputerResult<W>, IntCodeError> {
         self.is_done = false;
         self.output = Vec::new();
+        // outputs are collected as they happen, so just keep going
+        while let RunState::Output(_) = self.run()? {}
+
+        Ok(IntCodeComputerResult {
+            code: self.code.to_vec(),
+            output: self.output.clone(),
+        })
+    }
+
+    /**
+     * Runs until the next output, until the program halts or waits for
+     * input, or until the step limit for this call is used up.
+     */
+    pub fn run(&mut self) -> Result<RunState<W>, IntCodeError> {
+        let mut steps = 0;
         loop {
-            match self.code[self.instruction_pointer] % 100 {
-                1 => self.add(),
-                2 => self.multiply(),
-                3 => {
-                    if self.inputs.len() == 0 {
-                        // wait for new input
-                        break;
-                    }
-                    self.insert();
+            if let Some(limit) = self.step_limit {
+                if steps >= limit {
+                    return Ok(RunState::StepLimitReached);
                 }
-                4 => self.output(),
-                5 => self.jump_if_true(),
-                6 => self.jump_if_false(),
-                7 => self.less_than(),
-                8 => self.equals(),
-                9 => self.adjust_relative_base(),
-                99 => {
-                    self.is_done = true;
-                    break;
+            }
+            if let Some(state) = self.step()? {
+                return Ok(state);
+            }
+            steps += 1;
+        }
+    }
+
+    /**
+     * Executes a single instruction. Returns None if the program can just
+     * keep going, otherwise what stopped it. Waiting for input and halting
+     * leave the instruction pointer where it is.
+     */
+    pub fn step(&mut self) -> Result<Option<RunState<W>>, IntCodeError> {
+        if let Some(history) = &mut self.history {
+            history.begin(Change {
+                step: self.steps,
+                ip: self.instruction_pointer,
+                relative_base: self.relative_base,
+                was_done: self.is_done,
+                writes: Vec::new(),
+                input: None,
+                outputs: self.output.len(),
+            });
+        }
+        let result = self.execute();
+        if let Some(history) = &mut self.history {
+            history.finish(self.steps, self.is_done);
+        }
+        result
+    }
+
+    #[inline]
+    fn execute(&mut self) -> Result<Option<RunState<W>>, IntCodeError> {
+        let ip = self.instruction_pointer;
+        if let Some(watchdog) = &self.watchdog {
+            let checked = watchdog.check_step(self.steps);
+            self.watch(checked)?;
+        }
+        if !self.extensions.is_empty() {
+            if let Some(word) = self.code.get(ip).to_i64() {
+                if let Some(extension) = self.extensions.get(word % 100) {
+                    let parameters = extension.parameters;
+                    let handler = Arc::clone(&extension.handler);
+                    return self.run_extension(word, parameters, handler);
                 }
-                _any => {
-                    println!("uh oh, got input: {}", _any);
-                    panic!()
+            }
+        }
+        let decoded = self.fetch(ip)?;
+        let instruction = decoded.instruction;
+        let operands = match self.tracer {
+            Some(_) => Some(self.get_operands(decoded)?),
+            None => None,
+        };
+
+        let mut state = None;
+        match instruction.opcode {
+            Opcode::Add => self.add(decoded)?,
+            Opcode::Multiply => self.multiply(decoded)?,
+            Opcode::Input => {
+                if self.inputs.is_empty() {
+                    // wait for new input
+                    return Ok(Some(RunState::NeedsInput));
                 }
+                self.insert(decoded)?
+            }
+            Opcode::Output => state = Some(RunState::Output(self.output(decoded)?)),
+            Opcode::JumpIfTrue => self.jump_if_true(decoded)?,
+            Opcode::JumpIfFalse => self.jump_if_false(decoded)?,
+            Opcode::LessThan => self.less_than(decoded)?,
+            Opcode::Equals => self.equals(decoded)?,
+            Opcode::AdjustRelativeBase => self.adjust_relative_base(decoded)?,
+            Opcode::Halt => {
+                self.is_done = true;
+                return Ok(Some(RunState::Halted));
             }
         }
 
-        IntCodeComputerResult {
-            code: self.code.clone(),
-            output: self.output.clone(),
+        if let Some(profile) = &mut self.profile {
+            profile.record(ip, instruction.opcode, self.instruction_pointer);
+        }
+        if let Some((operands, positions)) = operands {
+            let write = if instruction.opcode.writes() {
+                let address = positions[operands.len() - 1];
+                Some((address, self.code.get(address)))
+            } else {
+                None
+            };
+            let event = TraceEvent {
+                step: self.steps,
+                ip,
+                instruction,
+                operands,
+                write,
+            };
+            if let Some(tracer) = &mut self.tracer {
+                tracer(&event);
+            }
+        }
+        self.steps += 1;
+        Ok(state)
+    }
+
+    /**
+     * Runs a registered opcode the way step() runs a built-in one. These
+     * don't show up in traces or the profile.
+     */
+    fn run_extension(
+        &mut self,
+        word: i64,
+        parameters: usize,
+        handler: Handler<W>,
+    ) -> Result<Option<RunState<W>>, IntCodeError> {
+        let ip = self.instruction_pointer;
+        let modes = decode_modes(word, ip, parameters)?;
+        let operands = self.operands(ip, modes, parameters)?;
+        let positions = self.resolve(operands, parameters)?;
+        let input = self.inputs.front().cloned();
+        let mut context = Context::new(self, modes, positions, parameters, input);
+        let flow = handler(&mut context)?;
+        if context.took_input() {
+            self.take_input();
+        }
+
+        let mut state = None;
+        match flow {
+            Flow::Continue => self.instruction_pointer = ip + parameters + 1,
+            Flow::Jump(address) => self.instruction_pointer = address,
+            Flow::Output(value) => {
+                self.emit(value.clone())?;
+                self.instruction_pointer = ip + parameters + 1;
+                state = Some(RunState::Output(value));
+            }
+            Flow::WaitForInput => return Ok(Some(RunState::NeedsInput)),
+            Flow::Halt => {
+                self.is_done = true;
+                return Ok(Some(RunState::Halted));
+            }
+        }
+        self.steps += 1;
+        Ok(state)
+    }
+
+    /**
+     * Adds an opcode on top of the built-in ones. Built-in opcodes can't
+     * be replaced and every opcode can only be registered once.
+     */
+    pub fn register_opcode(&mut self, extension: Extension<W>) -> Result<(), RegisterError> {
+        self.extensions.register(extension)
+    }
+
+    /**
+     * The values the instruction is about to read, with the destination
+     * address in place of a parameter that gets written to, and where
+     * each of them is.
+     */
+    fn get_operands(&self, decoded: Decoded) -> Result<(Vec<W>, [usize; 3]), IntCodeError> {
+        let instruction = decoded.instruction;
+        let positions = self.get_positions(decoded)?;
+        let parameter_count = instruction.opcode.parameter_count();
+        let operands = (0..parameter_count)
+            .map(|index| {
+                if instruction.opcode.writes() && index == parameter_count - 1 {
+                    W::from_i64(positions[index] as i64)
+                } else {
+                    self.code.get(positions[index])
+                }
+            })
+            .collect();
+        Ok((operands, positions))
+    }
+
+    /**
+     * Calls `tracer` with every instruction executed from now on, see
+     * trace::write_trace for logging to a file. None turns tracing off.
+     */
+    pub fn set_tracer(&mut self, tracer: Option<TraceCallback<W>>) {
+        self.tracer = tracer;
+    }
+
+    /**
+     * Calls `callback` with every output from now on. Unlike the outputs
+     * of process_code they don't start over with each call, and stepping
+     * back doesn't take them back.
+     */
+    pub fn subscribe(&mut self, callback: OutputCallback<W>) -> Subscription {
+        self.subscribers.add(callback)
+    }
+
+    /**
+     * Returns false if there was no such subscriber.
+     */
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        self.subscribers.remove(subscription)
+    }
+
+    /**
+     * Starts counting executions per address and opcode.
+     */
+    pub fn enable_profiling(&mut self) {
+        self.profile.get_or_insert_with(Profile::default);
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /**
+     * Limits how many instructions a single call to run() may execute,
+     * None to run until something else stops it.
+     */
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /**
+     * Puts a watchdog on the program: once it runs past one of `limits`
+     * every call to step() fails with IntCodeError::LimitReached. Limits
+     * count from here on and stay in place until set again, None removes
+     * them.
+     */
+    pub fn set_limits(&mut self, limits: Option<Limits>) {
+        self.watchdog = limits.map(|limits| Watchdog::new(limits, self.steps));
+    }
+
+    pub fn limits(&self) -> Option<Limits> {
+        self.watchdog.as_ref().map(Watchdog::limits)
+    }
+
+    /**
+     * Turns a limit the watchdog saw into an error carrying the current
+     * state.
+     */
+    fn watch(&self, checked: Result<(), Limit>) -> Result<(), IntCodeError> {
+        checked.map_err(|limit| IntCodeError::LimitReached {
+            ip: self.instruction_pointer,
+            limit,
+            snapshot: self.snapshot().narrow().map(Box::new),
+        })
+    }
+
+    /**
+     * Total number of instructions executed so far.
+     */
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /**
+     * Resolves the addresses of the instruction's parameters from their
+     * operands, see decode_at().
+     */
+    #[inline]
+    fn get_positions(&self, decoded: Decoded) -> Result<[usize; 3], IntCodeError> {
+        self.resolve(
+            decoded.operands,
+            decoded.instruction.opcode.parameter_count(),
+        )
+    }
+
+    #[inline]
+    fn resolve(&self, operands: [Operand; 3], count: usize) -> Result<[usize; 3], IntCodeError> {
+        let ip = self.instruction_pointer;
+        let mut positions: [usize; 3] = [0; 3];
+        for (position, operand) in positions.iter_mut().zip(operands.iter()).take(count) {
+            *position = match *operand {
+                Operand::Address(address) => address,
+                Operand::Relative(offset) => {
+                    to_address(ip, checked(ip, offset.checked_add(self.relative_base))?)?
+                }
+                Operand::Negative(address) => {
+                    return Err(IntCodeError::NegativeAddress { ip, address })
+                }
+            }
+        }
+        Ok(positions)
+    }
+
+    /**
+     * Decodes the instruction at `ip`, or takes it from the decode cache
+     * when that is turned on.
+     */
+    #[inline]
+    fn fetch(&mut self, ip: usize) -> Result<Decoded, IntCodeError> {
+        if let Some(decoded) = self.decode_cache.as_ref().and_then(|cache| cache.get(ip)) {
+            return Ok(decoded);
+        }
+        let decoded = self.decode_at(ip)?;
+        if let Some(cache) = &mut self.decode_cache {
+            cache.insert(ip, decoded);
         }
+        Ok(decoded)
+    }
+
+    fn decode_at(&self, ip: usize) -> Result<Decoded, IntCodeError> {
+        let instruction = decode(checked(ip, self.code.get(ip).to_i64())?, ip)?;
+        Ok(Decoded {
+            instruction,
+            operands: self.operands(ip, instruction.modes, instruction.opcode.parameter_count())?,
+        })
     }
 
     /**
-     * 0 => position mode
-     * 1 => immediate mode
-     * 2 => relative mode
+     * The operands of the instruction at `ip`. Only the parameters its
+     * first `count` modes read an address from have to fit into an i64,
+     * immediate ones are only ever read from memory.
      */
-    fn get_positions(&mut self, parameter_length: usize) -> [usize; 3] {
-        let opcode = self.code[self.instruction_pointer];
-        let modes: [usize; 3] = [
-            (opcode / 100 % 10) as usize,
-            (opcode / 1000 % 10) as usize,
-            (opcode / 10000 % 10) as usize,
-        ];
+    fn operands(
+        &self,
+        ip: usize,
+        modes: [Mode; 3],
+        count: usize,
+    ) -> Result<[Operand; 3], IntCodeError> {
+        let mut operands = [Operand::Address(0); 3];
+        for (index, operand) in operands.iter_mut().enumerate().take(count) {
+            let address = ip + index + 1;
+            if modes[index] == Mode::Immediate {
+                *operand = Operand::Address(address);
+                continue;
+            }
+            let parameter = checked(ip, self.code.get(address).to_i64())?;
+            *operand = match modes[index] {
+                Mode::Relative => Operand::Relative(parameter),
+                _ if parameter < 0 => Operand::Negative(parameter),
+                _ => Operand::Address(parameter as usize),
+            };
+        }
+        Ok(operands)
+    }
 
-        let mut positions: [usize; 3] = [0 as usize; 3];
-        for index in 0..parameter_length {
-            match modes[index] {
-                0 => positions[index] = self.code[self.instruction_pointer + index + 1] as usize,
-                1 => positions[index] = self.instruction_pointer + index + 1,
-                2 => {
-                    positions[index] = (self.code[self.instruction_pointer + index + 1]
-                        + self.relative_base) as usize
+    /**
+     * Turns caching of decoded instructions on or off. It is on by
+     * default and starts out with the whole program decoded up front, so
+     * nothing is decoded while it runs until it writes over its own code.
+     * Turning it off decodes every instruction again each time it runs.
+     */
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = None;
+        if enabled {
+            let mut cache = DecodeCache::default();
+            for ip in 0..self.program_length {
+                // a word that doesn't decode is data, which never runs
+                if let Ok(decoded) = self.decode_at(ip) {
+                    cache.insert(ip, decoded);
                 }
-                _ => panic!("Why did you get a mode that was not 1, 2, or 3"),
             }
+            self.decode_cache = Some(cache);
+        }
+    }
+
+    /**
+     * Captures everything needed to pick the run back up later: memory,
+     * instruction pointer, relative base and the input and output queues.
+     */
+    pub fn snapshot(&self) -> Snapshot<W> {
+        Snapshot {
+            memory: self.code.clone(),
+            instruction_pointer: self.instruction_pointer,
+            relative_base: self.relative_base,
+            steps: self.steps,
+            is_done: self.is_done,
+            program_length: self.program_length,
+            inputs: self.inputs.iter().cloned().collect(),
+            output: self.output.clone(),
+        }
+    }
+
+    /**
+     * Puts the computer back into the state of the snapshot. Step limit,
+     * tracer and profile are left alone.
+     */
+    pub fn restore(&mut self, snapshot: &Snapshot<W>) {
+        self.code = snapshot.memory.clone();
+        self.instruction_pointer = snapshot.instruction_pointer;
+        self.relative_base = snapshot.relative_base;
+        self.steps = snapshot.steps;
+        self.is_done = snapshot.is_done;
+        self.program_length = snapshot.program_length;
+        self.inputs = snapshot.inputs.iter().cloned().collect();
+        self.output = snapshot.output.clone();
+        // the memory changed wholesale, so start over with the cache
+        self.set_decode_cache(self.decode_cache.is_some());
+        // and the logged changes no longer lead back from here
+        if let Some(history) = &mut self.history {
+            history.clear();
         }
-        positions
     }
 
-    pub fn add_inputs(&mut self, inputs: Vec<i64>) {
-        for input in inputs.iter() {
-            self.inputs.push(*input);
+    /**
+     * A computer picking up where the snapshot left off. Its program as
+     * loaded, for reset(), is the program as it is in the snapshot.
+     */
+    pub fn from_snapshot(snapshot: Snapshot<W>) -> Self {
+        let image = (0..snapshot.program_length)
+            .map(|address| snapshot.memory.get(address))
+            .collect();
+        let mut computer = Self::unpack(snapshot, Arc::new(image));
+        computer.set_decode_cache(true);
+        computer
+    }
+
+    /**
+     * A computer in the state of the snapshot, with `image` as the
+     * program it was loaded with and no decode cache yet.
+     */
+    fn unpack(snapshot: Snapshot<W>, image: Arc<Vec<W>>) -> Self {
+        Self {
+            image,
+            code: snapshot.memory,
+            inputs: snapshot.inputs.into_iter().collect(),
+            instruction_pointer: snapshot.instruction_pointer,
+            is_done: snapshot.is_done,
+            output: snapshot.output,
+            relative_base: snapshot.relative_base,
+            step_limit: None,
+            steps: snapshot.steps,
+            program_length: snapshot.program_length,
+            tracer: None,
+            subscribers: Subscribers::default(),
+            profile: None,
+            decode_cache: None,
+            watchdog: None,
+            extensions: Extensions::default(),
+            history: None,
         }
     }
 
-    fn add(&mut self) {
-        let [address_1, address_2, address_3] = self.get_positions(3);
-        self.code[address_3] = self.code[address_1] + self.code[address_2];
+    /**
+     * The memory the program was loaded into, with whatever it has
+     * written there since.
+     */
+    pub fn program(&self) -> Vec<W> {
+        (0..self.program_length)
+            .map(|address| self.code.get(address))
+            .collect()
+    }
+
+    /**
+     * Caps how far the program may write into memory, None for no limit.
+     */
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.code.set_limit(limit);
+    }
+
+    #[inline]
+    fn write(&mut self, address: usize, value: W) -> Result<(), IntCodeError> {
+        let ip = self.instruction_pointer;
+        let old = self.history.as_ref().map(|_| self.code.get(address));
+        if let Err(error) = self.code.set(address, value) {
+            return Err(IntCodeError::MemoryLimitExceeded {
+                ip,
+                address: error.address,
+                limit: error.limit,
+                snapshot: self.snapshot().narrow().map(Box::new),
+            });
+        }
+        if let Some(cache) = &mut self.decode_cache {
+            cache.invalidate(address);
+        }
+        if let (Some(history), Some(old)) = (&mut self.history, old) {
+            history.record_write(address, old);
+        }
+        Ok(())
+    }
+
+    fn take_input(&mut self) {
+        if let Some(value) = self.inputs.pop_front() {
+            if let Some(history) = &mut self.history {
+                history.record_input(value);
+            }
+        }
+    }
+
+    /**
+     * Keeps an undo log of the last `capacity` instructions so they can be
+     * stepped back over, None to stop keeping one. Changing it forgets
+     * what was logged so far.
+     */
+    pub fn set_history(&mut self, capacity: Option<usize>) {
+        self.history = capacity.map(History::new);
+    }
+
+    pub fn history(&self) -> Option<&History<W>> {
+        self.history.as_ref()
+    }
+
+    /**
+     * Undoes the last instruction in the history: its writes, the jump,
+     * the relative base, the input it read and the output it produced,
+     * which no longer counts towards the output limit. Returns false once
+     * there is nothing left to undo. Outputs already handed to run_io and
+     * friends stay handed out.
+     */
+    pub fn step_back(&mut self) -> bool {
+        let change = match self.history.as_mut().and_then(History::pop) {
+            Some(change) => change,
+            None => return false,
+        };
+        for (address, old) in change.writes.into_iter().rev() {
+            // the address was written before, so it is within the limit
+            let _ = self.code.set(address, old);
+            if let Some(cache) = &mut self.decode_cache {
+                cache.invalidate(address);
+            }
+        }
+        if let Some(input) = change.input {
+            self.inputs.push_front(input);
+        }
+        if self.output.len() > change.outputs {
+            if let Some(watchdog) = &mut self.watchdog {
+                watchdog.uncount_output();
+            }
+        }
+        self.output.truncate(change.outputs);
+        self.instruction_pointer = change.ip;
+        self.relative_base = change.relative_base;
+        self.steps = change.step;
+        self.is_done = change.was_done;
+        true
+    }
+
+    /**
+     * Steps back to just before the last instruction in the history that
+     * wrote to `address` and returns where that instruction is. Without
+     * one, nothing is undone and None is returned.
+     */
+    pub fn reverse_to_write(&mut self, address: usize) -> Option<usize> {
+        let back = self.history.as_ref()?.last_write(address)?;
+        for _ in 0..back {
+            self.step_back();
+        }
+        Some(self.instruction_pointer)
+    }
+
+    pub fn add_inputs(&mut self, inputs: Vec<W>) {
+        self.inputs.extend(inputs);
+    }
+
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    pub fn set_instruction_pointer(&mut self, address: usize) {
+        self.instruction_pointer = address;
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    /**
+     * Inputs that have been added but not read by the program yet.
+     */
+    pub fn pending_inputs(&self) -> Vec<W> {
+        self.inputs.iter().cloned().collect()
+    }
+
+    /**
+     * Outputs produced since the last call to process_code.
+     */
+    pub fn outputs(&self) -> &[W] {
+        &self.output
+    }
+
+    pub fn peek(&self, address: usize) -> W {
+        self.code.get(address)
+    }
+
+    pub fn poke(&mut self, address: usize, value: W) -> Result<(), IntCodeError> {
+        self.write(address, value)
+    }
+
+    /**
+     * Writes each value to its address before a run, like the noun and
+     * verb of day 2.
+     */
+    pub fn patch(&mut self, patches: &[(usize, W)]) -> Result<(), IntCodeError> {
+        for (address, value) in patches.iter() {
+            self.write(*address, value.clone())?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn add(&mut self, decoded: Decoded) -> Result<(), IntCodeError> {
+        let [address_1, address_2, address_3] = self.get_positions(decoded)?;
+        let value = self
+            .code
+            .get(address_1)
+            .checked_sum(&self.code.get(address_2));
+        self.write(address_3, checked(self.instruction_pointer, value)?)?;
         self.instruction_pointer += 4;
+        Ok(())
     }
 
-    fn multiply(&mut self) {
-        let [address_1, address_2, address_3] = self.get_positions(3);
-        self.code[address_3] = self.code[address_1] * self.code[address_2];
+    #[inline]
+    fn multiply(&mut self, decoded: Decoded) -> Result<(), IntCodeError> {
+        let [address_1, address_2, address_3] = self.get_positions(decoded)?;
+        let value = self
+            .code
+            .get(address_1)
+            .checked_product(&self.code.get(address_2));
+        self.write(address_3, checked(self.instruction_pointer, value)?)?;
         self.instruction_pointer += 4;
+        Ok(())
     }
 
-    fn insert(&mut self) {
-        let [address_1, _, _] = self.get_positions(1);
-        self.code[address_1] = self.inputs[0];
-        self.inputs.drain(0..1);
+    #[inline]
+    fn insert(&mut self, decoded: Decoded) -> Result<(), IntCodeError> {
+        let [address_1, _, _] = self.get_positions(decoded)?;
+        let value = match self.inputs.front() {
+            Some(value) => value.clone(),
+            None => {
+                return Err(IntCodeError::InputExhausted {
+                    ip: self.instruction_pointer,
+                })
+            }
+        };
+        self.write(address_1, value)?;
+        self.take_input();
         self.instruction_pointer += 2;
+        Ok(())
     }
 
-    fn output(&mut self) {
-        let [address_1, _, _] = self.get_positions(1);
-        self.output.push(self.code[address_1]);
+    #[inline]
+    fn output(&mut self, decoded: Decoded) -> Result<W, IntCodeError> {
+        let [address_1, _, _] = self.get_positions(decoded)?;
+        let value = self.code.get(address_1);
+        self.emit(value.clone())?;
         self.instruction_pointer += 2;
+        Ok(value)
     }
 
-    fn jump_if_true(&mut self) {
-        let [address_1, address_2, _] = self.get_positions(2);
-        if self.code[address_1] != 0 {
-            self.instruction_pointer = self.code[address_2] as usize;
+    #[inline]
+    fn emit(&mut self, value: W) -> Result<(), IntCodeError> {
+        if let Some(watchdog) = &mut self.watchdog {
+            let checked = watchdog.check_output();
+            self.watch(checked)?;
+        }
+        self.output.push(value.clone());
+        if !self.subscribers.is_empty() {
+            self.subscribers.publish(&OutputEvent {
+                step: self.steps,
+                ip: self.instruction_pointer,
+                value,
+            });
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn jump_if_true(&mut self, decoded: Decoded) -> Result<(), IntCodeError> {
+        let [address_1, address_2, _] = self.get_positions(decoded)?;
+        if !self.code.get(address_1).is_zero() {
+            self.instruction_pointer =
+                to_address(self.instruction_pointer, self.read_i64(address_2)?)?;
         } else {
             self.instruction_pointer += 3;
         }
+        Ok(())
     }
 
-    fn jump_if_false(&mut self) {
-        let [address_1, address_2, _] = self.get_positions(2);
-        if self.code[address_1] == 0 {
-            self.instruction_pointer = self.code[address_2] as usize;
+    #[inline]
+    fn jump_if_false(&mut self, decoded: Decoded) -> Result<(), IntCodeError> {
+        let [address_1, address_2, _] = self.get_positions(decoded)?;
+        if self.code.get(address_1).is_zero() {
+            self.instruction_pointer =
+                to_address(self.instruction_pointer, self.read_i64(address_2)?)?;
         } else {
             self.instruction_pointer += 3;
         }
+        Ok(())
     }
 
-    fn less_than(&mut self) {
-        let [address_1, address_2, address_3] = self.get_positions(3);
-        if self.code[address_1] < self.code[address_2] {
-            self.code[address_3] = 1;
+    #[inline]
+    fn less_than(&mut self, decoded: Decoded) -> Result<(), IntCodeError> {
+        let [address_1, address_2, address_3] = self.get_positions(decoded)?;
+        if self.code.get(address_1) < self.code.get(address_2) {
+            self.write(address_3, W::from_i64(1))?;
         } else {
-            self.code[address_3] = 0;
+            self.write(address_3, W::from_i64(0))?;
         }
         self.instruction_pointer += 4;
+        Ok(())
     }
 
-    fn equals(&mut self) {
-        let [address_1, address_2, address_3] = self.get_positions(3);
-        if self.code[address_1] == self.code[address_2] {
-            self.code[address_3] = 1;
+    #[inline]
+    fn equals(&mut self, decoded: Decoded) -> Result<(), IntCodeError> {
+        let [address_1, address_2, address_3] = self.get_positions(decoded)?;
+        if self.code.get(address_1) == self.code.get(address_2) {
+            self.write(address_3, W::from_i64(1))?;
         } else {
-            self.code[address_3] = 0;
+            self.write(address_3, W::from_i64(0))?;
         }
         self.instruction_pointer += 4;
+        Ok(())
     }
 
-    fn adjust_relative_base(&mut self) {
-        let [address_1, _, _] = self.get_positions(1);
+    #[inline]
+    fn adjust_relative_base(&mut self, decoded: Decoded) -> Result<(), IntCodeError> {
+        let [address_1, _, _] = self.get_positions(decoded)?;
 
-        self.relative_base += self.code[address_1];
+        self.relative_base = checked(
+            self.instruction_pointer,
+            self.relative_base.checked_add(self.read_i64(address_1)?),
+        )?;
         self.instruction_pointer += 2;
+        Ok(())
+    }
+
+    /**
+     * The value at `address` where it has to be an i64, as a jump target
+     * or an offset to the relative base.
+     */
+    #[inline]
+    fn read_i64(&self, address: usize) -> Result<i64, IntCodeError> {
+        checked(self.instruction_pointer, self.code.get(address).to_i64())
+    }
+}
+
+/**
+ * Tracers and output subscribers can't be cloned, so the copy starts out
+ * without them. Everything else, including the step limit, the watchdog,
+ * registered opcodes, the history and the profile so far, is copied.
+ */
+impl<W: Word> Clone for IntCodeComputer<W> {
+    fn clone(&self) -> Self {
+        let mut computer = Self::unpack(self.snapshot(), Arc::clone(&self.image));
+        computer.decode_cache = self.decode_cache.clone();
+        computer.step_limit = self.step_limit;
+        computer.watchdog = self.watchdog.clone();
+        computer.extensions = self.extensions.clone();
+        computer.history = self.history.clone();
+        computer.profile = self.profile.clone();
+        computer
     }
 }
 
+/**
+ * Turns the result of a checked operation into an overflow error.
+ */
+#[inline]
+fn checked<T>(ip: usize, value: Option<T>) -> Result<T, IntCodeError> {
+    value.ok_or(IntCodeError::Overflow { ip })
+}
+
+#[inline]
+fn to_address(ip: usize, address: i64) -> Result<usize, IntCodeError> {
+    if address < 0 {
+        return Err(IntCodeError::NegativeAddress { ip, address });
+    }
+    Ok(address as usize)
+}
+
 #[cfg(test)]
 mod tests {
+    use super::super::assembler::assemble_to_string;
     use super::*;
 
     #[test]
     fn process_input_equal_to_8_position_mode() {
         let message = "3,9,8,9,10,9,4,9,99,-1,8";
-        let mut comp1 = IntCodeComputer::new(message);
-        let mut comp2 = IntCodeComputer::new(message);
+        let mut comp1 = IntCodeComputer::new(message).unwrap();
+        let mut comp2 = IntCodeComputer::new(message).unwrap();
         comp1.add_inputs(vec![8]);
         comp2.add_inputs(vec![1]);
 
         // should return 1 if input equal to 8, else 0
-        assert_eq!(comp1.process_code().output[0], 1);
-        assert_eq!(comp2.process_code().output[0], 0);
+        assert_eq!(comp1.process_code().unwrap().output[0], 1);
+        assert_eq!(comp2.process_code().unwrap().output[0], 0);
     }
 
     #[test]
     fn process_input_equal_to_8_immediate_mode() {
         let message = "3,3,1108,-1,8,3,4,3,99";
-        let mut comp1 = IntCodeComputer::new(message);
-        let mut comp2 = IntCodeComputer::new(message);
+        let mut comp1 = IntCodeComputer::new(message).unwrap();
+        let mut comp2 = IntCodeComputer::new(message).unwrap();
         comp1.add_inputs(vec![8]);
         comp2.add_inputs(vec![1]);
 
         // should return 1 if input equal to 8, else 0
-        assert_eq!(comp1.process_code().output[0], 1);
-        assert_eq!(comp2.process_code().output[0], 0);
+        assert_eq!(comp1.process_code().unwrap().output[0], 1);
+        assert_eq!(comp2.process_code().unwrap().output[0], 0);
     }
 
     #[test]
     fn process_input_less_than_8_position_mode() {
         let message = "3,9,7,9,10,9,4,9,99,-1,8";
-        let mut comp1 = IntCodeComputer::new(message);
-        let mut comp2 = IntCodeComputer::new(message);
+        let mut comp1 = IntCodeComputer::new(message).unwrap();
+        let mut comp2 = IntCodeComputer::new(message).unwrap();
         comp1.add_inputs(vec![1]);
         comp2.add_inputs(vec![10]);
 
         // should return 1 if input less than 8, else 0
-        assert_eq!(comp1.process_code().output[0], 1);
-        assert_eq!(comp2.process_code().output[0], 0);
+        assert_eq!(comp1.process_code().unwrap().output[0], 1);
+        assert_eq!(comp2.process_code().unwrap().output[0], 0);
     }
 
     #[test]
     fn process_input_less_than_8_immediate_mode() {
         let message = "3,3,1107,-1,8,3,4,3,99";
-        let mut comp1 = IntCodeComputer::new(message);
-        let mut comp2 = IntCodeComputer::new(message);
+        let mut comp1 = IntCodeComputer::new(message).unwrap();
+        let mut comp2 = IntCodeComputer::new(message).unwrap();
         comp1.add_inputs(vec![1]);
         comp2.add_inputs(vec![10]);
 
         // should return 1 if input less than 8, else 0
-        assert_eq!(comp1.process_code().output[0], 1);
-        assert_eq!(comp2.process_code().output[0], 0);
+        assert_eq!(comp1.process_code().unwrap().output[0], 1);
+        assert_eq!(comp2.process_code().unwrap().output[0], 0);
     }
 
     #[test]
     fn process_jump_position_mode() {
         let message = "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9";
-        let mut comp1 = IntCodeComputer::new(message);
-        let mut comp2 = IntCodeComputer::new(message);
+        let mut comp1 = IntCodeComputer::new(message).unwrap();
+        let mut comp2 = IntCodeComputer::new(message).unwrap();
         comp1.add_inputs(vec![0]);
         comp2.add_inputs(vec![-12]);
 
         // output 0 if the input was zero or 1 if the input was non-zero:
-        assert_eq!(comp1.process_code().output[0], 0);
-        assert_eq!(comp2.process_code().output[0], 1);
+        assert_eq!(comp1.process_code().unwrap().output[0], 0);
+        assert_eq!(comp2.process_code().unwrap().output[0], 1);
     }
 
     #[test]
     fn process_jump_immediate_mode() {
         let message = "3,3,1105,-1,9,1101,0,0,12,4,12,99,1";
-        let mut comp1 = IntCodeComputer::new(message);
-        let mut comp2 = IntCodeComputer::new(message);
+        let mut comp1 = IntCodeComputer::new(message).unwrap();
+        let mut comp2 = IntCodeComputer::new(message).unwrap();
         comp1.add_inputs(vec![0]);
         comp2.add_inputs(vec![-12]);
 
         // output 0 if the input was zero or 1 if the input was non-zero:
-        assert_eq!(comp1.process_code().output[0], 0);
-        assert_eq!(comp2.process_code().output[0], 1);
+        assert_eq!(comp1.process_code().unwrap().output[0], 0);
+        assert_eq!(comp2.process_code().unwrap().output[0], 1);
     }
 
     #[test]
     fn process_large_message() {
         let message = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
-        let mut comp1 = IntCodeComputer::new(message);
-        let mut comp2 = IntCodeComputer::new(message);
-        let mut comp3 = IntCodeComputer::new(message);
+        let mut comp1 = IntCodeComputer::new(message).unwrap();
+        let mut comp2 = IntCodeComputer::new(message).unwrap();
+        let mut comp3 = IntCodeComputer::new(message).unwrap();
         comp1.add_inputs(vec![7]);
         comp2.add_inputs(vec![8]);
         comp3.add_inputs(vec![9]);
@@ -271,32 +1217,270 @@ mod tests {
         // The program will then output 999 if the input value is below 8,
         // output 1000 if the input value is equal to 8,
         // or output 1001 if the input value is greater than 8.
-        assert_eq!(comp1.process_code().output[0], 999);
-        assert_eq!(comp2.process_code().output[0], 1000);
-        assert_eq!(comp3.process_code().output[0], 1001);
+        assert_eq!(comp1.process_code().unwrap().output[0], 999);
+        assert_eq!(comp2.process_code().unwrap().output[0], 1000);
+        assert_eq!(comp3.process_code().unwrap().output[0], 1001);
     }
 
     #[test]
     fn large_output() {
         let message = "104,1125899906842624,99";
-        let mut comp = IntCodeComputer::new(message);
+        let mut comp = IntCodeComputer::new(message).unwrap();
 
-        assert_eq!(comp.process_code().output[0], 1125899906842624);
+        assert_eq!(comp.process_code().unwrap().output[0], 1125899906842624);
     }
 
     #[test]
     fn copy_self_test() {
         let message = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
-        let mut comp = IntCodeComputer::new(message);
+        let mut comp = IntCodeComputer::new(message).unwrap();
 
-        assert_eq!(comp.process_code().output[0], 109);
+        assert_eq!(comp.process_code().unwrap().output[0], 109);
     }
 
     #[test]
     fn moar_tests() {
         let message = "1102,34915192,34915192,7,4,7,99,0";
-        let mut comp = IntCodeComputer::new(message);
+        let mut comp = IntCodeComputer::new(message).unwrap();
+
+        assert_eq!(comp.process_code().unwrap().output[0], 1219070632396864);
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let mut comp = IntCodeComputer::new("1102,4611686018427387904,2,0,99").unwrap();
+        assert_eq!(comp.process_code(), Err(IntCodeError::Overflow { ip: 0 }));
+
+        let mut comp = IntCodeComputer::new("109,9223372036854775807,22201,1,0,0,99").unwrap();
+        assert_eq!(comp.process_code(), Err(IntCodeError::Overflow { ip: 2 }));
+    }
+
+    #[test]
+    fn invalid_opcode_is_an_error() {
+        let mut comp = IntCodeComputer::new("1101,1,1,5,42,0").unwrap();
+
+        assert_eq!(
+            comp.process_code(),
+            Err(IntCodeError::InvalidOpcode { ip: 4, opcode: 42 })
+        );
+    }
+
+    #[test]
+    fn invalid_mode_is_an_error() {
+        let mut comp = IntCodeComputer::new("304,1,99").unwrap();
+
+        assert_eq!(
+            comp.process_code(),
+            Err(IntCodeError::InvalidMode { ip: 0, mode: 3 })
+        );
+    }
+
+    #[test]
+    fn negative_address_is_an_error() {
+        let mut comp = IntCodeComputer::new("109,-5,204,0,99").unwrap();
+
+        assert_eq!(
+            comp.process_code(),
+            Err(IntCodeError::NegativeAddress { ip: 2, address: -5 })
+        );
+    }
+
+    #[test]
+    fn write_in_immediate_mode_is_an_error() {
+        let mut comp = IntCodeComputer::new("11101,1,1,0,99").unwrap();
+
+        assert_eq!(
+            comp.process_code(),
+            Err(IntCodeError::WriteInImmediateMode { ip: 0 })
+        );
+    }
+
+    #[test]
+    fn parse_failure_is_an_error() {
+        let result = IntCodeComputer::new("1,0,x,0,99");
+
+        assert_eq!(
+            result.err(),
+            Some(IntCodeError::Parse {
+                ip: 2,
+                value: String::from("x"),
+            })
+        );
+    }
+
+    #[test]
+    fn writes_far_past_the_program() {
+        let message = "1101,5,6,1000000000,4,1000000000,4,2000,99";
+        let mut comp = IntCodeComputer::new(message).unwrap();
+
+        assert_eq!(comp.process_code().unwrap().output, vec![11, 0]);
+    }
+
+    #[test]
+    fn memory_limit_is_an_error() {
+        let mut comp = IntCodeComputer::new("3,100,1101,5,6,100,4,100,99").unwrap();
+        comp.set_memory_limit(Some(64));
+        comp.add_inputs(vec![7, 8]);
+
+        let snapshot = match comp.process_code() {
+            Err(IntCodeError::MemoryLimitExceeded {
+                ip: 0,
+                address: 100,
+                limit: 64,
+                snapshot: Some(snapshot),
+            }) => snapshot,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(snapshot.instruction_pointer, 0);
+        assert_eq!(snapshot.inputs, vec![7, 8]);
+        assert_eq!(snapshot.memory.limit(), Some(64));
+
+        // picks up where it stopped once there is more room
+        let mut comp = IntCodeComputer::from_snapshot(*snapshot);
+        comp.set_memory_limit(Some(128));
+        assert_eq!(comp.process_code().unwrap().output, vec![11]);
+        assert_eq!(comp.pending_inputs(), vec![8]);
+    }
+
+    #[test]
+    fn run_stops_at_each_output() {
+        let message = "3,11,104,1,4,11,1001,11,1,11,99,0";
+        let mut comp = IntCodeComputer::new(message).unwrap();
+
+        assert_eq!(comp.run(), Ok(RunState::NeedsInput));
+        comp.add_inputs(vec![41]);
+        assert_eq!(comp.run(), Ok(RunState::Output(1)));
+        assert_eq!(comp.run(), Ok(RunState::Output(41)));
+        assert_eq!(comp.run(), Ok(RunState::Halted));
+        assert_eq!(comp.run(), Ok(RunState::Halted));
+        assert_eq!(comp.steps(), 4);
+        assert!(comp.is_done);
+    }
+
+    #[test]
+    fn step_runs_one_instruction() {
+        let mut comp = IntCodeComputer::new("1101,2,3,5,104,0,99").unwrap();
+
+        assert_eq!(comp.step(), Ok(None));
+        assert_eq!(comp.step(), Ok(Some(RunState::Output(5))));
+        assert_eq!(comp.step(), Ok(Some(RunState::Halted)));
+    }
+
+    #[test]
+    fn run_respects_the_step_limit() {
+        // loops forever
+        let mut comp = IntCodeComputer::new("1105,1,0").unwrap();
+        comp.set_step_limit(Some(10));
+
+        assert_eq!(comp.run(), Ok(RunState::StepLimitReached));
+        assert_eq!(comp.steps(), 10);
+    }
+
+    #[test]
+    fn counts_down_in_a_loop() {
+        let message = assemble_to_string(
+            "
+                    IN n
+            loop:   OUT n
+                    ADD n, #-1, n
+                    JT n, #loop
+                    HLT
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut comp = IntCodeComputer::new(&message).unwrap();
+        comp.add_inputs(vec![3]);
+
+        assert_eq!(comp.process_code().unwrap().output, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn relative_base_addresses_a_stack() {
+        // pushes the input twice onto a stack and pops the sum
+        let message = assemble_to_string(
+            "
+                    ARB #stack
+                    IN [0]
+                    ADD [0], #0, [1]
+                    ARB #1
+                    ADD [-1], [0], [-1]
+                    ARB #-1
+                    OUT [0]
+                    HLT
+            stack:  .data 0, 0
+            ",
+        )
+        .unwrap();
+        let mut comp = IntCodeComputer::new(&message).unwrap();
+        comp.add_inputs(vec![21]);
+
+        assert_eq!(comp.process_code().unwrap().output, vec![42]);
+    }
+
+    #[test]
+    fn self_modifying_code_runs_with_and_without_the_cache() {
+        // bumps the parameter of its own OUT instruction every time around
+        let message = assemble_to_string(
+            "
+            again:  OUT #1
+                    ADD again+1, #1, again+1
+                    ADD n, #-1, n
+                    JT n, #again
+                    HLT
+            n:      .data 3
+            ",
+        )
+        .unwrap();
+
+        for &cached in [true, false].iter() {
+            let mut comp = IntCodeComputer::new(&message).unwrap();
+            comp.set_decode_cache(cached);
+            assert_eq!(comp.process_code().unwrap().output, vec![1, 2, 3]);
+
+            // turning the OUT into a HLT takes effect as well
+            comp.poke(0, 99).unwrap();
+            comp.set_instruction_pointer(0);
+            assert_eq!(comp.process_code().unwrap().output, vec![]);
+            assert!(comp.is_done);
+        }
+    }
+
+    #[test]
+    fn reset_goes_back_to_the_loaded_program() {
+        // doubles its input into cell 9 and outputs it
+        let program = vec![3, 9, 102, 2, 9, 9, 4, 9, 99, 0];
+        let mut comp = IntCodeComputer::from_program(program.clone());
+        comp.add_inputs(vec![5, 6]);
+        assert_eq!(comp.process_code().unwrap().output, vec![10]);
+        assert_eq!(comp.peek(9), 10);
+
+        comp.reset();
+        assert_eq!(comp.program(), program);
+        assert_eq!(comp.pending_inputs(), vec![]);
+        assert_eq!(comp.steps(), 0);
+        assert!(!comp.is_done);
+        comp.add_inputs(vec![4]);
+        assert_eq!(comp.process_code().unwrap().output, vec![8]);
+    }
+
+    #[test]
+    fn load_patched_shares_the_program() {
+        let mut base = IntCodeComputer::new("1,0,0,0,99,30,40,50").unwrap();
+        base.set_limits(Some(Limits {
+            instructions: Some(10),
+            ..Limits::default()
+        }));
+        base.poke(0, 2).unwrap();
+
+        let mut comp = base.load_patched(&[(1, 5), (2, 7)]).unwrap();
+        assert_eq!(comp.limits(), base.limits());
+        comp.process_code().unwrap();
+        // base's own changes don't carry over, only the loaded program
+        assert_eq!(comp.peek(0), 80);
 
-        assert_eq!(comp.process_code().output[0], 1219070632396864);
+        comp.reset();
+        assert_eq!(comp.program(), vec![1, 0, 0, 0, 99, 30, 40, 50]);
+        assert!(Arc::ptr_eq(&comp.image, &base.image));
     }
 }
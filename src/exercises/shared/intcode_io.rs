@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, StdinLock, Stdout, Write};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::time::Duration;
+
+/**
+ * Where an IntCodeComputer gets its inputs from and sends its outputs to,
+ * see IntCodeComputer::run_io.
+ */
+pub trait IntcodeIo {
+    /**
+     * The next input, or None if there is nothing to read right now. The
+     * computer then stops with RunState::NeedsInput and can be resumed
+     * later.
+     */
+    fn input(&mut self) -> Option<i64>;
+
+    fn output(&mut self, value: i64);
+}
+
+/**
+ * Inputs and outputs kept in plain queues.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueIo {
+    pub inputs: VecDeque<i64>,
+    pub outputs: VecDeque<i64>,
+}
+
+impl QueueIo {
+    pub fn new(inputs: Vec<i64>) -> Self {
+        Self {
+            inputs: inputs.into_iter().collect(),
+            outputs: VecDeque::new(),
+        }
+    }
+}
+
+impl IntcodeIo for QueueIo {
+    fn input(&mut self) -> Option<i64> {
+        self.inputs.pop_front()
+    }
+
+    fn output(&mut self, value: i64) {
+        self.outputs.push_back(value);
+    }
+}
+
+/**
+ * Inputs and outputs handled by a pair of closures.
+ */
+pub struct FnIo<I, O> {
+    input: I,
+    output: O,
+}
+
+impl<I: FnMut() -> Option<i64>, O: FnMut(i64)> FnIo<I, O> {
+    pub fn new(input: I, output: O) -> Self {
+        Self { input, output }
+    }
+}
+
+impl<I: FnMut() -> Option<i64>, O: FnMut(i64)> IntcodeIo for FnIo<I, O> {
+    fn input(&mut self) -> Option<i64> {
+        (self.input)()
+    }
+
+    fn output(&mut self, value: i64) {
+        (self.output)(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Wait {
+    Forever,
+    Never,
+    Timeout(Duration),
+}
+
+/**
+ * Inputs received from and outputs sent to mpsc channels, which is how
+ * computers get wired to each other or to other threads. Outputs to a
+ * receiver that has gone away are dropped.
+ */
+pub struct ChannelIo {
+    receiver: Receiver<i64>,
+    sender: Sender<i64>,
+    wait: Wait,
+}
+
+impl ChannelIo {
+    /**
+     * Blocks on input until a value arrives or every sender is gone.
+     */
+    pub fn new(receiver: Receiver<i64>, sender: Sender<i64>) -> Self {
+        Self {
+            receiver,
+            sender,
+            wait: Wait::Forever,
+        }
+    }
+
+    /**
+     * Never blocks, so several computers can take turns on one thread.
+     */
+    pub fn non_blocking(receiver: Receiver<i64>, sender: Sender<i64>) -> Self {
+        Self {
+            receiver,
+            sender,
+            wait: Wait::Never,
+        }
+    }
+
+    pub fn with_timeout(receiver: Receiver<i64>, sender: Sender<i64>, timeout: Duration) -> Self {
+        Self {
+            receiver,
+            sender,
+            wait: Wait::Timeout(timeout),
+        }
+    }
+}
+
+impl IntcodeIo for ChannelIo {
+    fn input(&mut self) -> Option<i64> {
+        match self.wait {
+            Wait::Forever => self.receiver.recv().ok(),
+            Wait::Never => match self.receiver.try_recv() {
+                Ok(value) => Some(value),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+            },
+            Wait::Timeout(timeout) => match self.receiver.recv_timeout(timeout) {
+                Ok(value) => Some(value),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+            },
+        }
+    }
+
+    fn output(&mut self, value: i64) {
+        let _ = self.sender.send(value);
+    }
+}
+
+/**
+ * Reads one number per line and writes one number per line, e.g. for a
+ * terminal. Input runs out at the end of the reader or at a line that
+ * isn't a number, blank lines are skipped.
+ */
+pub struct LineIo<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: BufRead, W: Write> LineIo<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Self { reader, writer }
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: BufRead, W: Write> IntcodeIo for LineIo<R, W> {
+    fn input(&mut self) -> Option<i64> {
+        // whatever was printed so far should be visible before we wait
+        let _ = self.writer.flush();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) if line.trim().is_empty() => continue,
+                Ok(_) => return line.trim().parse().ok(),
+            }
+        }
+    }
+
+    fn output(&mut self, value: i64) {
+        let _ = writeln!(self.writer, "{}", value);
+    }
+}
+
+pub type StdIo = LineIo<StdinLock<'static>, Stdout>;
+
+pub fn stdio() -> StdIo {
+    LineIo::new(io::stdin().lock(), io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::int_code_computer::{IntCodeComputer, RunState};
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    // doubles every input
+    const DOUBLER: &str = "3,11,1002,11,2,11,4,11,1105,1,0";
+
+    #[test]
+    fn reads_and_writes_queues_and_closures() {
+        let mut comp = IntCodeComputer::new(DOUBLER).unwrap();
+        let mut io = QueueIo::new(vec![1, 2, 3]);
+        assert_eq!(comp.run_io(&mut io), Ok(RunState::NeedsInput));
+        assert_eq!(io.outputs, vec![2, 4, 6]);
+
+        let mut next = 10;
+        let mut seen = Vec::new();
+        let mut io = FnIo::new(
+            || {
+                next -= 5;
+                if next > 0 {
+                    Some(next)
+                } else {
+                    None
+                }
+            },
+            |value| seen.push(value),
+        );
+        assert_eq!(comp.run_io(&mut io), Ok(RunState::NeedsInput));
+        assert_eq!(seen, vec![10]);
+    }
+
+    #[test]
+    fn connects_computers_across_threads() {
+        let (to_first, first_input) = mpsc::channel();
+        let (to_second, second_input) = mpsc::channel();
+        let (to_main, results) = mpsc::channel();
+
+        let first = thread::spawn(move || {
+            let mut comp = IntCodeComputer::new(DOUBLER).unwrap();
+            comp.run_io(&mut ChannelIo::new(first_input, to_second))
+        });
+        let second = thread::spawn(move || {
+            let mut comp = IntCodeComputer::new(DOUBLER).unwrap();
+            comp.run_io(&mut ChannelIo::new(second_input, to_main))
+        });
+
+        for value in 1..=3 {
+            to_first.send(value).unwrap();
+        }
+        let received: Vec<i64> = results.iter().take(3).collect();
+        assert_eq!(received, vec![4, 8, 12]);
+
+        // hanging up lets both computers run out of input
+        drop(to_first);
+        assert_eq!(first.join().unwrap(), Ok(RunState::NeedsInput));
+        assert_eq!(second.join().unwrap(), Ok(RunState::NeedsInput));
+    }
+
+    #[test]
+    fn talks_line_by_line() {
+        let mut comp = IntCodeComputer::new(DOUBLER).unwrap();
+        let mut io = LineIo::new("4\n\n-7\nquit\n8\n".as_bytes(), Vec::new());
+        assert_eq!(comp.run_io(&mut io), Ok(RunState::NeedsInput));
+
+        let (_, written) = io.into_inner();
+        assert_eq!(String::from_utf8(written).unwrap(), "8\n-14\n");
+    }
+}
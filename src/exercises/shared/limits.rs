@@ -0,0 +1,179 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/**
+ * How often the clock is looked at, reading it on every instruction
+ * would slow everything down noticeably.
+ */
+const CLOCK_INTERVAL: u64 = 1024;
+
+/**
+ * Caps on a single run, see IntCodeComputer::set_limits. Each one is
+ * counted from the moment the limits are set. Memory is capped with
+ * IntCodeComputer::set_memory_limit instead, going past it returns the
+ * state as well, with IntCodeError::MemoryLimitExceeded.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Limits {
+    pub instructions: Option<u64>,
+    pub time: Option<Duration>,
+    pub outputs: Option<u64>,
+}
+
+/**
+ * The limit that stopped a program.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Limit {
+    Instructions(u64),
+    Time(Duration),
+    Outputs(u64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions(count) => write!(f, "limit of {} instructions", count),
+            Limit::Time(time) => write!(f, "time limit of {:?}", time),
+            Limit::Outputs(count) => write!(f, "limit of {} outputs", count),
+        }
+    }
+}
+
+/**
+ * Keeps track of a running program against its Limits.
+ */
+#[derive(Clone, Debug)]
+pub struct Watchdog {
+    limits: Limits,
+    started: Instant,
+    first_step: u64,
+    outputs: u64,
+}
+
+impl Watchdog {
+    pub fn new(limits: Limits, steps: u64) -> Self {
+        Self {
+            limits,
+            started: Instant::now(),
+            first_step: steps,
+            outputs: 0,
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /**
+     * Whether the program may run another instruction, given how many it
+     * has run in total.
+     */
+    pub fn check_step(&self, steps: u64) -> Result<(), Limit> {
+        // stepping back can take a program to before the limits were set
+        let taken = steps.saturating_sub(self.first_step);
+        if let Some(limit) = self.limits.instructions {
+            if taken >= limit {
+                return Err(Limit::Instructions(limit));
+            }
+        }
+        if let Some(limit) = self.limits.time {
+            // is_multiple_of needs Rust 1.87
+            #[allow(clippy::manual_is_multiple_of)]
+            if taken % CLOCK_INTERVAL == 0 && self.started.elapsed() >= limit {
+                return Err(Limit::Time(limit));
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Counts an output, unless that would be one too many.
+     */
+    pub fn check_output(&mut self) -> Result<(), Limit> {
+        if let Some(limit) = self.limits.outputs {
+            if self.outputs >= limit {
+                return Err(Limit::Outputs(limit));
+            }
+        }
+        self.outputs += 1;
+        Ok(())
+    }
+
+    /**
+     * Takes back an output that was stepped back over.
+     */
+    pub fn uncount_output(&mut self) {
+        self.outputs = self.outputs.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::int_code_computer::{IntCodeComputer, IntCodeError};
+    use super::*;
+
+    fn stopped_by(message: &str, limits: Limits) -> (usize, Limit) {
+        let mut comp = IntCodeComputer::new(message).unwrap();
+        comp.set_limits(Some(limits));
+        match comp.process_code() {
+            Err(IntCodeError::LimitReached {
+                ip,
+                limit,
+                snapshot,
+            }) => {
+                assert_eq!(
+                    snapshot.map(|snapshot| snapshot.instruction_pointer),
+                    Some(ip)
+                );
+                (ip, limit)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stops_endless_loops() {
+        // jumps to itself forever
+        let endless = "1105,1,0";
+        let limits = Limits {
+            instructions: Some(100),
+            ..Limits::default()
+        };
+        assert_eq!(stopped_by(endless, limits), (0, Limit::Instructions(100)));
+
+        let limits = Limits {
+            time: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        assert_eq!(
+            stopped_by(endless, limits),
+            (0, Limit::Time(Duration::from_millis(10)))
+        );
+    }
+
+    #[test]
+    fn stops_runaway_outputs() {
+        // outputs 1 forever
+        let limits = Limits {
+            outputs: Some(3),
+            ..Limits::default()
+        };
+        assert_eq!(stopped_by("104,1,1105,1,0", limits), (0, Limit::Outputs(3)));
+
+        // outputs that were stepped back over don't count
+        let mut comp = IntCodeComputer::new("104,1,1105,1,0").unwrap();
+        comp.set_history(Some(10));
+        comp.set_limits(Some(limits));
+        for _ in 0..5 {
+            comp.step().unwrap();
+        }
+        assert!(comp.step_back() && comp.step_back());
+        for _ in 0..2 {
+            comp.step().unwrap();
+        }
+        assert_eq!(comp.outputs(), &[1, 1, 1]);
+        comp.step().unwrap();
+        assert!(comp.step().is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+/**
+ * Addresses below this are kept in a vector that grows on demand,
+ * anything above lives in a hash map so a single far away write doesn't
+ * allocate gigabytes of zeroes.
+ */
+const DENSE_LIMIT: usize = 1 << 20;
+
+/**
+ * Memory cells hold an i64 unless the computer works on a wider
+ * word::Word.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Memory<T = i64> {
+    dense: Vec<T>,
+    sparse: HashMap<usize, T>,
+    limit: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryLimitExceeded {
+    pub address: usize,
+    pub limit: usize,
+}
+
+impl<T: Clone + Default> Memory<T> {
+    pub fn new(program: Vec<T>) -> Self {
+        Self {
+            dense: program,
+            sparse: HashMap::new(),
+            limit: None,
+        }
+    }
+
+    /**
+     * Caps the addresses that can be written to. Reads are never limited,
+     * untouched cells simply read as 0.
+     */
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    #[inline]
+    pub fn get(&self, address: usize) -> T {
+        if address < self.dense.len() {
+            self.dense[address].clone()
+        } else if address < DENSE_LIMIT {
+            T::default()
+        } else {
+            self.sparse.get(&address).cloned().unwrap_or_default()
+        }
+    }
+
+    #[inline]
+    pub fn set(&mut self, address: usize, value: T) -> Result<(), MemoryLimitExceeded> {
+        if let Some(limit) = self.limit {
+            if address >= limit {
+                return Err(MemoryLimitExceeded { address, limit });
+            }
+        }
+
+        if address < self.dense.len() {
+            self.dense[address] = value;
+        } else if address < DENSE_LIMIT {
+            self.dense.resize(address + 1, T::default());
+            self.dense[address] = value;
+        } else {
+            self.sparse.insert(address, value);
+        }
+        Ok(())
+    }
+
+    /**
+     * One past the highest address that has been allocated.
+     */
+    pub fn len(&self) -> usize {
+        match self.sparse.keys().max() {
+            Some(address) => address + 1,
+            None => self.dense.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /**
+     * The contiguous low part of memory, which holds the program and
+     * anything written near it.
+     */
+    pub fn to_vec(&self) -> Vec<T> {
+        self.dense.clone()
+    }
+
+    pub fn dense(&self) -> &[T] {
+        &self.dense
+    }
+
+    /**
+     * Cells far enough out to be stored sparsely, ordered by address.
+     */
+    pub fn sparse(&self) -> Vec<(usize, T)> {
+        let mut cells: Vec<(usize, T)> = self
+            .sparse
+            .iter()
+            .map(|(address, value)| (*address, value.clone()))
+            .collect();
+        cells.sort_unstable_by_key(|(address, _)| *address);
+        cells
+    }
+
+    /**
+     * The same memory with every cell converted by `convert`, None as soon
+     * as one of them can't be.
+     */
+    pub fn try_map<U, F>(&self, convert: F) -> Option<Memory<U>>
+    where
+        F: Fn(&T) -> Option<U>,
+    {
+        Some(Memory {
+            dense: self.dense.iter().map(&convert).collect::<Option<_>>()?,
+            sparse: self
+                .sparse
+                .iter()
+                .map(|(address, value)| Some((*address, convert(value)?)))
+                .collect::<Option<_>>()?,
+            limit: self.limit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn untouched_cells_read_as_zero() {
+        let memory = Memory::new(vec![1, 2, 3]);
+
+        assert_eq!(memory.get(1), 2);
+        assert_eq!(memory.get(3), 0);
+        assert_eq!(memory.get(1_000_000_000), 0);
+    }
+
+    #[test]
+    fn grows_on_write() {
+        let mut memory = Memory::new(vec![1, 2, 3]);
+        memory.set(10, 7).unwrap();
+        memory.set(1_000_000_000, 8).unwrap();
+
+        assert_eq!(memory.get(10), 7);
+        assert_eq!(memory.get(1_000_000_000), 8);
+        assert_eq!(memory.to_vec().len(), 11);
+        assert_eq!(memory.len(), 1_000_000_001);
+    }
+
+    #[test]
+    fn programs_longer_than_the_dense_part_stay_writable() {
+        let mut memory = Memory::new(vec![1; DENSE_LIMIT + 2]);
+        memory.set(DENSE_LIMIT, 7).unwrap();
+        memory.set(DENSE_LIMIT + 5, 8).unwrap();
+
+        assert_eq!(memory.get(DENSE_LIMIT), 7);
+        assert_eq!(memory.get(DENSE_LIMIT + 1), 1);
+        assert_eq!(memory.get(DENSE_LIMIT + 5), 8);
+        assert_eq!(memory.len(), DENSE_LIMIT + 6);
+    }
+
+    #[test]
+    fn writes_past_the_limit_are_rejected() {
+        let mut memory = Memory::new(vec![1, 2, 3]);
+        memory.set_limit(Some(8));
+
+        assert_eq!(memory.set(7, 1), Ok(()));
+        assert_eq!(
+            memory.set(8, 1),
+            Err(MemoryLimitExceeded {
+                address: 8,
+                limit: 8
+            })
+        );
+    }
+}
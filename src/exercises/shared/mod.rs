@@ -1 +1,23 @@
+pub mod analyzer;
+pub mod ascii;
+pub mod assembler;
+pub mod bench;
+pub mod compiler;
+pub mod debugger;
+pub mod decompiler;
+pub mod disassembler;
+pub mod events;
+pub mod extension;
+pub mod fuzzer;
+pub mod history;
+pub mod instruction;
 pub mod int_code_computer;
+pub mod intcode_io;
+pub mod limits;
+pub mod memory;
+pub mod network;
+pub mod optimizer;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+pub mod word;
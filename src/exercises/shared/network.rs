@@ -0,0 +1,534 @@
+use super::int_code_computer::{IntCodeComputer, IntCodeError, RunState};
+use super::intcode_io::IntcodeIo;
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+/**
+ * How the outputs of the machines in a Network get routed.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Topology {
+    /**
+     * Every machine feeds the next one, the last one feeds the outside.
+     */
+    Pipeline,
+    /**
+     * Like a pipeline, but the last machine feeds the first one again.
+     */
+    Ring,
+    /**
+     * Each output of machine `i` is copied to every machine in `edges[i]`.
+     * Machines without edges feed the outside.
+     */
+    Graph(Vec<Vec<usize>>),
+    /**
+     * Machines send packets of three outputs: destination address, x and
+     * y. Each machine first gets its own address as input and then
+     * `idle_input` whenever it asks for input while none is waiting.
+     * Packets to `nat` are kept by the network, and once every machine
+     * sits idle the last of them is sent to machine 0. Packets to any
+     * other unknown address feed the outside.
+     */
+    Packets { idle_input: i64, nat: Option<i64> },
+}
+
+/**
+ * Why a Network stopped running.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NetworkState {
+    Halted,
+    /**
+     * A packet network has nothing left to do and no NAT to wake it.
+     */
+    Idle,
+    /**
+     * The NAT just woke the network up by sending this x and y to
+     * machine 0. Running again carries on from there.
+     */
+    NatDelivered(i64, i64),
+    /**
+     * This machine used up its step limit before the network was done,
+     * the first one if several did. Running again carries on from there.
+     */
+    StepLimitReached(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkError {
+    Machine {
+        node: usize,
+        error: IntCodeError,
+    },
+    /**
+     * No machine can make progress, but not all of them have halted.
+     */
+    Deadlock {
+        waiting: Vec<usize>,
+    },
+    /**
+     * Packet networks need the round-robin scheduler to notice when they
+     * go idle, so they can't run on threads.
+     */
+    ThreadedPackets,
+    /**
+     * The thread of a machine panicked in run_threaded() and took the
+     * machine with it. It is back to where it was before the run.
+     */
+    Panicked {
+        node: usize,
+    },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Machine { node, error } => write!(f, "machine {}: {}", node, error),
+            NetworkError::Deadlock { waiting } => {
+                write!(f, "deadlock, machines {:?} wait for input forever", waiting)
+            }
+            NetworkError::ThreadedPackets => {
+                write!(f, "packet networks can only run round-robin")
+            }
+            NetworkError::Panicked { node } => write!(f, "machine {} panicked", node),
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+/**
+ * A number of IntCodeComputers wired together by a Topology.
+ *
+ * run() takes turns between the machines on the current thread, giving
+ * each at most `slice` instructions per turn. run_threaded() gives every
+ * machine its own thread instead.
+ */
+pub struct Network {
+    machines: Vec<IntCodeComputer>,
+    topology: Topology,
+    slice: u64,
+    history: Vec<Vec<i64>>,
+    packets: Vec<Vec<i64>>,
+    external: Vec<i64>,
+    nat_packets: Vec<(i64, i64)>,
+}
+
+impl Network {
+    pub fn new(machines: Vec<IntCodeComputer>, topology: Topology) -> Self {
+        let mut machines = machines;
+        if let Topology::Packets { .. } = topology {
+            for (address, machine) in machines.iter_mut().enumerate() {
+                machine.add_inputs(vec![address as i64]);
+            }
+        }
+        Self {
+            history: vec![Vec::new(); machines.len()],
+            packets: vec![Vec::new(); machines.len()],
+            machines,
+            topology,
+            slice: 10_000,
+            external: Vec::new(),
+            nat_packets: Vec::new(),
+        }
+    }
+
+    /**
+     * How many instructions a machine may run per turn before the next
+     * one gets to go.
+     */
+    pub fn set_slice(&mut self, slice: u64) {
+        self.slice = slice.max(1);
+    }
+
+    pub fn machine(&self, node: usize) -> &IntCodeComputer {
+        &self.machines[node]
+    }
+
+    /**
+     * Queues inputs for one machine, e.g. phase settings or a first
+     * packet.
+     */
+    pub fn send(&mut self, node: usize, values: Vec<i64>) {
+        self.machines[node].add_inputs(values);
+    }
+
+    /**
+     * Everything machine `node` has output so far.
+     */
+    pub fn outputs_of(&self, node: usize) -> &[i64] {
+        &self.history[node]
+    }
+
+    pub fn last_output(&self, node: usize) -> Option<i64> {
+        self.history[node].last().copied()
+    }
+
+    /**
+     * Outputs that left the network, in the order they were produced.
+     */
+    pub fn external_outputs(&self) -> &[i64] {
+        &self.external
+    }
+
+    /**
+     * Every packet sent to the NAT so far.
+     */
+    pub fn nat_packets(&self) -> &[(i64, i64)] {
+        &self.nat_packets
+    }
+
+    fn targets(&self, node: usize) -> Vec<usize> {
+        let count = self.machines.len();
+        match &self.topology {
+            Topology::Pipeline if node + 1 < count => vec![node + 1],
+            Topology::Ring => vec![(node + 1) % count],
+            Topology::Graph(edges) => edges.get(node).cloned().unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn route(&mut self, node: usize, value: i64) {
+        self.history[node].push(value);
+        let nat = match self.topology {
+            Topology::Packets { nat, .. } => nat,
+            _ => {
+                let targets = self.targets(node);
+                if targets.is_empty() {
+                    self.external.push(value);
+                }
+                for target in targets {
+                    self.machines[target].add_inputs(vec![value]);
+                }
+                return;
+            }
+        };
+
+        self.packets[node].push(value);
+        if self.packets[node].len() < 3 {
+            return;
+        }
+        let packet: Vec<i64> = self.packets[node].drain(..).collect();
+        let (address, x, y) = (packet[0], packet[1], packet[2]);
+        if Some(address) == nat {
+            self.nat_packets.push((x, y));
+        } else if address >= 0 && (address as usize) < self.machines.len() {
+            self.machines[address as usize].add_inputs(vec![x, y]);
+        } else {
+            self.external.extend(packet);
+        }
+    }
+
+    /**
+     * Gives one machine its turn. Returns how many instructions it ran and
+     * whether it ended up waiting for input that isn't there.
+     */
+    fn turn(&mut self, node: usize) -> Result<(u64, bool), NetworkError> {
+        let idle_input = match self.topology {
+            Topology::Packets { idle_input, .. } => Some(idle_input),
+            _ => None,
+        };
+        let mut fed_idle_input = false;
+        let mut steps = 0;
+        let mut outputs = Vec::new();
+        let mut blocked = false;
+        while steps < self.slice && !self.machines[node].is_done {
+            let machine = &mut self.machines[node];
+            match machine.step() {
+                Ok(Some(RunState::Output(value))) => outputs.push(value),
+                Ok(Some(RunState::NeedsInput)) => match idle_input {
+                    Some(value) if !fed_idle_input => {
+                        machine.add_inputs(vec![value]);
+                        fed_idle_input = true;
+                        continue;
+                    }
+                    _ => {
+                        blocked = true;
+                        break;
+                    }
+                },
+                Ok(Some(_)) => break,
+                Ok(None) => {}
+                Err(error) => return Err(NetworkError::Machine { node, error }),
+            }
+            steps += 1;
+        }
+        for value in outputs {
+            self.route(node, value);
+        }
+        Ok((steps, blocked))
+    }
+
+    /**
+     * Runs every machine round-robin until all of them have halted, the
+     * packet network goes idle or the NAT wakes it up again.
+     */
+    pub fn run(&mut self) -> Result<NetworkState, NetworkError> {
+        loop {
+            let mut steps = 0;
+            let mut all_blocked = true;
+            let sent = self.history.iter().map(Vec::len).sum::<usize>();
+            for node in 0..self.machines.len() {
+                let (taken, blocked) = self.turn(node)?;
+                steps += taken;
+                all_blocked &= blocked || self.machines[node].is_done;
+            }
+            let sent = self.history.iter().map(Vec::len).sum::<usize>() - sent;
+
+            if self.machines.iter().all(|machine| machine.is_done) {
+                return Ok(NetworkState::Halted);
+            }
+            match self.topology {
+                Topology::Packets { .. } => {
+                    if !all_blocked || sent > 0 {
+                        continue;
+                    }
+                    match self.nat_packets.last() {
+                        Some(&(x, y)) => {
+                            self.machines[0].add_inputs(vec![x, y]);
+                            return Ok(NetworkState::NatDelivered(x, y));
+                        }
+                        _ => return Ok(NetworkState::Idle),
+                    }
+                }
+                _ if steps == 0 => {
+                    return Err(NetworkError::Deadlock {
+                        waiting: self.waiting(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn waiting(&self) -> Vec<usize> {
+        (0..self.machines.len())
+            .filter(|&node| !self.machines[node].is_done)
+            .collect()
+    }
+
+    /**
+     * Runs every machine on its own thread until all of them have halted.
+     * A machine that gets no input for `timeout` gives up, and if any
+     * machine had to give up the network counts as deadlocked. One that
+     * runs out of steps stops the network with StepLimitReached instead.
+     */
+    pub fn run_threaded(&mut self, timeout: Duration) -> Result<NetworkState, NetworkError> {
+        if let Topology::Packets { .. } = self.topology {
+            return Err(NetworkError::ThreadedPackets);
+        }
+
+        let (senders, receivers): (Vec<Sender<i64>>, Vec<Receiver<i64>>) =
+            self.machines.iter().map(|_| mpsc::channel()).unzip();
+        let targets: Vec<Vec<usize>> = (0..senders.len()).map(|node| self.targets(node)).collect();
+        let (to_outside, from_outside) = mpsc::channel();
+        let mut handles = Vec::new();
+        for ((machine, receiver), targets) in self.machines.drain(..).zip(receivers).zip(targets) {
+            let mut io = ThreadIo {
+                receiver,
+                targets: targets
+                    .iter()
+                    .map(|&target| senders[target].clone())
+                    .collect(),
+                outside: to_outside.clone(),
+                timeout,
+                history: Vec::new(),
+            };
+            let mut machine = machine;
+            // stands in for the machine if its thread panics
+            let fallback = machine.clone();
+            let handle = thread::spawn(move || {
+                let result = machine.run_io(&mut io);
+                (machine, io, result)
+            });
+            handles.push((handle, fallback));
+        }
+        // only the machines themselves may keep the channels open
+        drop(senders);
+        drop(to_outside);
+
+        let mut result = Ok(NetworkState::Halted);
+        let mut out_of_steps = None;
+        for (node, (handle, fallback)) in handles.into_iter().enumerate() {
+            let (mut machine, io, state) = match handle.join() {
+                Ok(joined) => joined,
+                Err(_) => {
+                    if result.is_ok() {
+                        result = Err(NetworkError::Panicked { node });
+                    }
+                    self.machines.push(fallback);
+                    continue;
+                }
+            };
+            // values nobody got around to reading stay queued
+            machine.add_inputs(io.receiver.try_iter().collect());
+            self.history[node].extend(io.history);
+            match (state, &result) {
+                (Err(error), Ok(_)) => result = Err(NetworkError::Machine { node, error }),
+                (Ok(RunState::StepLimitReached), _) if out_of_steps.is_none() => {
+                    out_of_steps = Some(node)
+                }
+                _ => {}
+            }
+            self.machines.push(machine);
+        }
+        self.external.extend(from_outside.try_iter());
+
+        if let (Some(node), Ok(_)) = (out_of_steps, &result) {
+            result = Ok(NetworkState::StepLimitReached(node));
+        }
+        if result == Ok(NetworkState::Halted)
+            && self.machines.iter().any(|machine| !machine.is_done)
+        {
+            result = Err(NetworkError::Deadlock {
+                waiting: self.waiting(),
+            });
+        }
+        result
+    }
+}
+
+struct ThreadIo {
+    receiver: Receiver<i64>,
+    targets: Vec<Sender<i64>>,
+    outside: Sender<i64>,
+    timeout: Duration,
+    history: Vec<i64>,
+}
+
+impl IntcodeIo for ThreadIo {
+    fn input(&mut self) -> Option<i64> {
+        match self.receiver.recv_timeout(self.timeout) {
+            Ok(value) => Some(value),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    fn output(&mut self, value: i64) {
+        self.history.push(value);
+        if self.targets.is_empty() {
+            let _ = self.outside.send(value);
+        }
+        for target in self.targets.iter() {
+            let _ = target.send(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble_to_string;
+    use super::*;
+
+    // doubles a single input, then halts
+    const DOUBLE_ONCE: &str = "3,9,102,2,9,9,4,9,99,0";
+
+    fn machines(code: &str, count: usize) -> Vec<IntCodeComputer> {
+        (0..count)
+            .map(|_| IntCodeComputer::new(code).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn runs_a_pipeline_round_robin_and_on_threads() {
+        let mut network = Network::new(machines(DOUBLE_ONCE, 3), Topology::Pipeline);
+        network.send(0, vec![5]);
+        assert_eq!(network.run(), Ok(NetworkState::Halted));
+        assert_eq!(network.external_outputs(), &[40]);
+        assert_eq!(network.last_output(1), Some(20));
+
+        let mut network = Network::new(machines(DOUBLE_ONCE, 3), Topology::Pipeline);
+        network.send(0, vec![5]);
+        let state = network.run_threaded(Duration::from_secs(5));
+        assert_eq!(state, Ok(NetworkState::Halted));
+        assert_eq!(network.external_outputs(), &[40]);
+    }
+
+    #[test]
+    fn detects_deadlock() {
+        let mut network = Network::new(machines(DOUBLE_ONCE, 2), Topology::Ring);
+        assert_eq!(
+            network.run(),
+            Err(NetworkError::Deadlock {
+                waiting: vec![0, 1]
+            })
+        );
+
+        let mut network = Network::new(machines(DOUBLE_ONCE, 2), Topology::Ring);
+        assert_eq!(
+            network.run_threaded(Duration::from_millis(10)),
+            Err(NetworkError::Deadlock {
+                waiting: vec![0, 1]
+            })
+        );
+    }
+
+    #[test]
+    fn reports_threads_that_run_out_of_steps_or_panic() {
+        // counts up forever
+        let mut counters = machines("1001,5,1,5,1105,1,0", 2);
+        for counter in counters.iter_mut() {
+            counter.set_step_limit(Some(10));
+        }
+        let mut network = Network::new(counters, Topology::Pipeline);
+        assert_eq!(
+            network.run_threaded(Duration::from_millis(10)),
+            Ok(NetworkState::StepLimitReached(0))
+        );
+        assert_eq!(network.machine(1).steps(), 10);
+
+        let mut doublers = machines(DOUBLE_ONCE, 2);
+        doublers[1].subscribe(Box::new(|_| panic!("subscriber gave up")));
+        let mut network = Network::new(doublers, Topology::Pipeline);
+        network.send(0, vec![5]);
+        assert_eq!(
+            network.run_threaded(Duration::from_secs(5)),
+            Err(NetworkError::Panicked { node: 1 })
+        );
+        assert_eq!(network.machine(0).steps(), 3);
+        assert_eq!(network.machine(1).steps(), 0);
+    }
+
+    #[test]
+    fn wakes_an_idle_packet_network_through_the_nat() {
+        // sends every packet it gets to the NAT with y increased by one
+        let code = assemble_to_string(
+            "
+                    IN addr
+            loop:   IN x
+                    EQ x, #-1, idle
+                    JT idle, #loop
+                    IN y
+                    ADD y, #1, y
+                    OUT #255
+                    OUT x
+                    OUT y
+                    JT #1, #loop
+            addr:   .data 0
+            x:      .data 0
+            y:      .data 0
+            idle:   .data 0
+            ",
+        )
+        .unwrap();
+        let topology = Topology::Packets {
+            idle_input: -1,
+            nat: Some(255),
+        };
+
+        let mut network = Network::new(machines(&code, 2), topology.clone());
+        assert_eq!(network.run(), Ok(NetworkState::Idle));
+
+        network.send(0, vec![7, 10]);
+        assert_eq!(network.run(), Ok(NetworkState::NatDelivered(7, 11)));
+        assert_eq!(network.run(), Ok(NetworkState::NatDelivered(7, 12)));
+        assert_eq!(network.nat_packets(), &[(7, 11), (7, 12)]);
+        assert_eq!(
+            Network::new(machines(&code, 1), topology).run_threaded(Duration::from_millis(1)),
+            Err(NetworkError::ThreadedPackets)
+        );
+    }
+}
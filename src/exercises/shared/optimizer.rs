@@ -0,0 +1,497 @@
+use super::analyzer::{analyze, constant_writes, Analysis};
+use super::instruction::{Instruction, Mode, Opcode};
+use super::int_code_computer::{IntCodeComputer, IntCodeError};
+use super::limits::Limits;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rewrite {
+    /**
+     * An instruction on two immediates turned into `ADD #value, #0`.
+     */
+    Fold { address: usize, value: i64 },
+    /**
+     * A jump to an unconditional jump sent straight on to where that goes.
+     */
+    Thread {
+        address: usize,
+        from: usize,
+        to: usize,
+    },
+    /**
+     * A jump that never does anything, taken out of the program.
+     */
+    Remove { address: usize },
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rewrite::Fold { address, value } => write!(f, "{}: folded to {}", address, value),
+            Rewrite::Thread { address, from, to } => {
+                write!(f, "{}: jumps to {} instead of {}", address, to, from)
+            }
+            Rewrite::Remove { address } => write!(f, "{}: removed", address),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Optimized {
+    pub code: Vec<i64>,
+    /**
+     * Addresses are those of the original program.
+     */
+    pub rewrites: Vec<Rewrite>,
+}
+
+/**
+ * Whether the program reads or writes anywhere that isn't known up
+ * front. A relative parameter could point at any instruction, whether it
+ * writes there or reads it as data.
+ */
+fn accesses_anywhere(analysis: &Analysis) -> bool {
+    analysis.instructions.values().any(|instruction| {
+        instruction.modes[..instruction.opcode.parameter_count()].contains(&Mode::Relative)
+    })
+}
+
+/**
+ * Whether control can get to a word that isn't a known instruction. That
+ * is usually code the program writes before running it, which makes
+ * everything the analysis found suspect.
+ */
+fn runs_into_unknown_code(code: &[i64], analysis: &Analysis) -> bool {
+    analysis
+        .instructions
+        .iter()
+        .any(|(&address, &instruction)| {
+            let next = address + instruction.opcode.size();
+            let mut targets = Vec::new();
+            match (instruction.opcode, constant(code, address, instruction)) {
+                (Opcode::Halt, _) => {}
+                (Opcode::JumpIfTrue, taken) | (Opcode::JumpIfFalse, taken) => {
+                    if taken != Some(false) {
+                        targets.extend(immediate_target(code, address, instruction));
+                    }
+                    if taken != Some(true) {
+                        targets.push(next);
+                    }
+                }
+                _ => targets.push(next),
+            }
+            targets
+                .iter()
+                .any(|target| !analysis.instructions.contains_key(target))
+        })
+}
+
+/**
+ * Whether every address the program uses is spelled out in a parameter,
+ * so the code can be moved around by fixing those up. Jumps through
+ * memory, relative parameters and writes into the code all rule that out.
+ */
+fn relocatable(analysis: &Analysis) -> bool {
+    analysis.indirect_jumps.is_empty()
+        && analysis.code_writes.is_empty()
+        && analysis.instructions.values().all(|instruction| {
+            instruction.modes[..instruction.opcode.parameter_count()]
+                .iter()
+                .all(|&mode| mode != Mode::Relative)
+        })
+}
+
+fn is_jump(instruction: Instruction) -> bool {
+    matches!(instruction.opcode, Opcode::JumpIfTrue | Opcode::JumpIfFalse)
+}
+
+/**
+ * Whether the jump at `address` is always (Some(true)) or never taken.
+ */
+fn constant(code: &[i64], address: usize, instruction: Instruction) -> Option<bool> {
+    match instruction.modes[0] {
+        Mode::Immediate => {
+            Some((code[address + 1] != 0) == (instruction.opcode == Opcode::JumpIfTrue))
+        }
+        _ => None,
+    }
+}
+
+fn immediate_target(code: &[i64], address: usize, instruction: Instruction) -> Option<usize> {
+    match (instruction.modes[1], code[address + 2]) {
+        (Mode::Immediate, target) if target >= 0 => Some(target as usize),
+        _ => None,
+    }
+}
+
+/**
+ * Rewrites a program into one that does the same in fewer steps:
+ * arithmetic on immediates is folded, jumps to unconditional jumps go
+ * straight to the end of the chain, and jumps that go nowhere are taken
+ * out. Taking instructions out moves the code after them, so that only
+ * happens when relocatable() says every address can be fixed up.
+ *
+ * Nothing that the program may write to or reads as data is touched. A
+ * write through a relative parameter could go anywhere, and running into
+ * something that isn't an instruction means the program writes its own
+ * code, so both leave the program as it is. With jumps through memory
+ * the program is only as well known as analyzer::reachable() makes it.
+ */
+pub fn optimize(code: &[i64]) -> Optimized {
+    let analysis = analyze(code);
+    let mut optimized = Optimized {
+        code: code.to_vec(),
+        rewrites: Vec::new(),
+    };
+    if accesses_anywhere(&analysis) || runs_into_unknown_code(code, &analysis) {
+        return optimized;
+    }
+    let written = constant_writes(code, &analysis.instructions);
+    let read = read_as_data(code, &analysis);
+    let untouched = |address: usize| {
+        let size = analysis.instructions[&address].opcode.size();
+        (address..address + size).all(|word| !written.contains(&word) && !read.contains(&word))
+    };
+    let jumps = |address: usize| {
+        analysis
+            .instructions
+            .get(&address)
+            .filter(|&&instruction| is_jump(instruction) && untouched(address))
+            .copied()
+    };
+
+    for (&address, &instruction) in analysis.instructions.iter() {
+        let folds = matches!(
+            instruction.opcode,
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals
+        ) && instruction.modes[0] == Mode::Immediate
+            && instruction.modes[1] == Mode::Immediate
+            && untouched(address);
+        if !folds {
+            continue;
+        }
+        let (a, b) = (code[address + 1], code[address + 2]);
+        let value = match instruction.opcode {
+            Opcode::Add => a.checked_add(b),
+            Opcode::Multiply => a.checked_mul(b),
+            Opcode::LessThan => Some((a < b) as i64),
+            _ => Some((a == b) as i64),
+        };
+        // overflows are left for the program to run into
+        if let Some(value) = value {
+            if code[address..address + 3] != [1101, value, 0] {
+                optimized.code[address..address + 3].copy_from_slice(&[1101, value, 0]);
+                optimized.rewrites.push(Rewrite::Fold { address, value });
+            }
+        }
+    }
+
+    for (&address, &instruction) in analysis.instructions.iter() {
+        if jumps(address).is_none() || constant(code, address, instruction) == Some(false) {
+            continue;
+        }
+        let from = match immediate_target(code, address, instruction) {
+            Some(target) => target,
+            None => continue,
+        };
+        let mut to = from;
+        let mut seen = BTreeSet::new();
+        while let Some(next) = jumps(to) {
+            if constant(code, to, next) != Some(true) || !seen.insert(to) {
+                break;
+            }
+            match immediate_target(code, to, next) {
+                Some(target) => to = target,
+                None => break,
+            }
+        }
+        if to != from {
+            optimized.code[address + 2] = to as i64;
+            optimized
+                .rewrites
+                .push(Rewrite::Thread { address, from, to });
+        }
+    }
+
+    if relocatable(&analysis) {
+        compact(&analysis, &mut optimized, &written, &read);
+    }
+    optimized
+}
+
+/**
+ * The cells read through a position parameter, whose values have to stay
+ * as they are and where they are.
+ */
+fn read_as_data(code: &[i64], analysis: &Analysis) -> BTreeSet<usize> {
+    analysis
+        .instructions
+        .iter()
+        .flat_map(|(&address, instruction)| {
+            (0..instruction.opcode.parameter_count())
+                .filter(move |&index| {
+                    instruction.modes[index] == Mode::Position
+                        && !(instruction.opcode.writes()
+                            && index == instruction.opcode.parameter_count() - 1)
+                })
+                .map(move |index| code[address + index + 1])
+        })
+        .filter(|&address| address >= 0)
+        .map(|address| address as usize)
+        .collect()
+}
+
+/**
+ * Takes out the jumps that are never taken or only go to the next
+ * instruction, and moves everything after them up.
+ */
+fn compact(
+    analysis: &Analysis,
+    optimized: &mut Optimized,
+    written: &BTreeSet<usize>,
+    read: &BTreeSet<usize>,
+) {
+    let code = optimized.code.clone();
+    let removed: BTreeSet<usize> = analysis
+        .instructions
+        .iter()
+        .filter(|(&address, &instruction)| {
+            let next = address + instruction.opcode.size();
+            is_jump(instruction)
+                && (address..next).all(|word| !written.contains(&word) && !read.contains(&word))
+                && (constant(&code, address, instruction) == Some(false)
+                    || immediate_target(&code, address, instruction) == Some(next))
+        })
+        .flat_map(|(&address, instruction)| address..address + instruction.opcode.size())
+        .collect();
+    if removed.is_empty() {
+        return;
+    }
+
+    // a removed word moves to wherever the next word that stays ends up
+    let relocate = |address: i64| {
+        if address < 0 || address as usize >= code.len() {
+            return address;
+        }
+        address - removed.range(..address as usize).count() as i64
+    };
+    let mut relocated = code.clone();
+    for (&address, instruction) in analysis.instructions.iter() {
+        for index in 0..instruction.opcode.parameter_count() {
+            let is_target = is_jump(*instruction) && index == 1;
+            if instruction.modes[index] == Mode::Position || is_target {
+                relocated[address + index + 1] = relocate(code[address + index + 1]);
+            }
+        }
+    }
+    optimized.code = relocated
+        .into_iter()
+        .enumerate()
+        .filter(|(address, _)| !removed.contains(address))
+        .map(|(_, word)| word)
+        .collect();
+    for (&address, instruction) in analysis.instructions.iter() {
+        if removed.contains(&address) && is_jump(*instruction) {
+            optimized.rewrites.push(Rewrite::Remove { address });
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Ending {
+    Halted,
+    NeedsInput,
+    LimitReached,
+    Failed,
+}
+
+/**
+ * How a run went, as far as can be compared between two versions of a
+ * program. Errors are only told apart from the other endings, their
+ * addresses differ once the code has moved.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Outcome {
+    pub output: Vec<i64>,
+    pub ending: Ending,
+}
+
+impl Outcome {
+    /**
+     * Two runs agree if they end the same way with the same output. A run
+     * that was cut off by the instruction limit only has to agree as far
+     * as it got.
+     */
+    pub fn agrees_with(&self, other: &Outcome) -> bool {
+        if self.ending == Ending::LimitReached || other.ending == Ending::LimitReached {
+            let shared = self.output.len().min(other.output.len());
+            self.output[..shared] == other.output[..shared]
+        } else {
+            self == other
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Mismatch {
+    pub inputs: Vec<i64>,
+    pub original: Outcome,
+    pub optimized: Outcome,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "with inputs {:?} the original gives {:?} and the optimized program {:?}",
+            self.inputs, self.original, self.optimized
+        )
+    }
+}
+
+impl Error for Mismatch {}
+
+/**
+ * Runs `code` on `inputs` with an IntCodeComputer, for at most `limit`
+ * instructions.
+ */
+pub fn run(code: &[i64], inputs: &[i64], limit: u64) -> Outcome {
+    let mut computer = IntCodeComputer::from_program(code.to_vec());
+    computer.set_limits(Some(Limits {
+        instructions: Some(limit),
+        ..Limits::default()
+    }));
+    computer.add_inputs(inputs.to_vec());
+    let ending = match computer.process_code() {
+        Ok(_) if computer.is_done => Ending::Halted,
+        Ok(_) => Ending::NeedsInput,
+        Err(IntCodeError::LimitReached { .. }) => Ending::LimitReached,
+        Err(_) => Ending::Failed,
+    };
+    Outcome {
+        output: computer.outputs().to_vec(),
+        ending,
+    }
+}
+
+/**
+ * Runs the original and the optimized program on every set of inputs and
+ * reports the first one they disagree on.
+ */
+pub fn differential(
+    original: &[i64],
+    optimized: &[i64],
+    inputs: &[Vec<i64>],
+    limit: u64,
+) -> Result<(), Mismatch> {
+    for inputs in inputs.iter() {
+        let expected = run(original, inputs, limit);
+        let actual = run(optimized, inputs, limit);
+        if !expected.agrees_with(&actual) {
+            return Err(Mismatch {
+                inputs: inputs.clone(),
+                original: expected,
+                optimized: actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::*;
+    use crate::utils;
+
+    #[test]
+    fn folds_threads_and_removes() {
+        let code = assemble(
+            "
+                    ADD #2, #3, x
+                    JT x, #hop
+                    HLT
+            hop:    JT #1, #end
+            end:    OUT x
+                    HLT
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let optimized = optimize(&code);
+        assert_eq!(
+            optimized.code,
+            vec![1101, 5, 0, 11, 1005, 11, 8, 99, 4, 11, 99, 0]
+        );
+        assert_eq!(
+            optimized.rewrites,
+            vec![
+                Rewrite::Fold {
+                    address: 0,
+                    value: 5
+                },
+                Rewrite::Thread {
+                    address: 4,
+                    from: 8,
+                    to: 11
+                },
+                Rewrite::Remove { address: 8 },
+            ]
+        );
+        assert_eq!(differential(&code, &optimized.code, &[vec![]], 100), Ok(()));
+    }
+
+    #[test]
+    fn leaves_modified_code_alone() {
+        let code = assemble(
+            "
+                    ADD #1, #1, fold+1
+            fold:   ADD #7, #3, x
+                    OUT x
+                    HLT
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let optimized = optimize(&code);
+        assert_eq!(
+            optimized.rewrites,
+            vec![Rewrite::Fold {
+                address: 0,
+                value: 2
+            }]
+        );
+        assert_eq!(differential(&code, &optimized.code, &[vec![]], 100), Ok(()));
+
+        // a relative write could hit anything
+        let code = vec![109, 1, 21101, 1, 2, 0, 99];
+        assert_eq!(optimize(&code).code, code);
+
+        // and a relative read could see the 2 of the ADD
+        let code = vec![109, 3, 1101, 2, 3, 9, 204, 0, 99, 0];
+        assert_eq!(optimize(&code).code, code);
+        assert_eq!(run(&code, &[], 100).output, vec![2]);
+
+        // outputs the 2 of the ADD, which folding would turn into a 5
+        let code = vec![1101, 2, 3, 7, 4, 1, 99, 0];
+        assert_eq!(optimize(&code).code, code);
+        assert_eq!(run(&code, &[], 100).output, vec![2]);
+    }
+
+    #[test]
+    fn keeps_day_5_working() {
+        let message = utils::read_file_into_string("./src/exercises/data/data-day5.txt");
+        let code = super::super::int_code_computer::read_code(&message).unwrap();
+        // it writes the instruction at 6 before running it
+        let optimized = optimize(&code);
+        assert_eq!(optimized.rewrites, vec![]);
+        let inputs: Vec<Vec<i64>> = (0..10).map(|input| vec![input]).collect();
+        assert_eq!(
+            differential(&code, &optimized.code, &inputs, 100_000),
+            Ok(())
+        );
+        assert_eq!(run(&optimized.code, &[5], 100_000).output, vec![9265694]);
+    }
+}
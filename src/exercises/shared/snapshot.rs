@@ -0,0 +1,285 @@
+use super::memory::Memory;
+use super::word::Word;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+const HEADER: &str = "intcode-snapshot 1";
+
+/**
+ * The full state of an IntCodeComputer at one point in time. Restoring it
+ * puts the computer back exactly where it was, see
+ * IntCodeComputer::snapshot and IntCodeComputer::restore.
+ *
+ * Snapshots are saved as plain text, one field per line:
+ *
+ * ```text
+ * intcode-snapshot 1
+ * ip 4
+ * relative_base 0
+ * steps 2
+ * halted false
+ * program_length 6
+ * memory_limit none
+ * inputs 7,8
+ * outputs 1
+ * memory 3,5,104,1,99,7
+ * cell 1000000000 42
+ * ```
+ *
+ * `memory` holds the contiguous low part of memory and each `cell` line
+ * one of the far away cells.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot<W = i64> {
+    pub memory: Memory<W>,
+    pub instruction_pointer: usize,
+    pub relative_base: i64,
+    pub steps: u64,
+    pub is_done: bool,
+    pub program_length: usize,
+    pub inputs: Vec<W>,
+    pub output: Vec<W>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Format { line: usize, message: String },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "could not read snapshot: {}", error),
+            SnapshotError::Format { line, message } => {
+                write!(f, "bad snapshot on line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+fn join<W: fmt::Display>(values: &[W]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    values.join(",")
+}
+
+impl<W: Word> fmt::Display for Snapshot<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "ip {}", self.instruction_pointer)?;
+        writeln!(f, "relative_base {}", self.relative_base)?;
+        writeln!(f, "steps {}", self.steps)?;
+        writeln!(f, "halted {}", self.is_done)?;
+        writeln!(f, "program_length {}", self.program_length)?;
+        match self.memory.limit() {
+            Some(limit) => writeln!(f, "memory_limit {}", limit)?,
+            None => writeln!(f, "memory_limit none")?,
+        }
+        writeln!(f, "inputs {}", join(&self.inputs))?;
+        writeln!(f, "outputs {}", join(&self.output))?;
+        writeln!(f, "memory {}", join(self.memory.dense()))?;
+        for (address, value) in self.memory.sparse() {
+            writeln!(f, "cell {} {}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: FromStr>(value: &str, line: usize) -> Result<T, SnapshotError> {
+    value.parse::<T>().map_err(|_| SnapshotError::Format {
+        line,
+        message: format!("could not parse {:?}", value),
+    })
+}
+
+fn parse_list<W: FromStr>(value: &str, line: usize) -> Result<Vec<W>, SnapshotError> {
+    if value.is_empty() {
+        return Ok(Vec::new());
+    }
+    value.split(',').map(|value| parse(value, line)).collect()
+}
+
+impl<W: Word> FromStr for Snapshot<W> {
+    type Err = SnapshotError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => {
+                return Err(SnapshotError::Format {
+                    line: 1,
+                    message: format!("expected {:?}", HEADER),
+                })
+            }
+        }
+
+        let mut snapshot = Snapshot {
+            memory: Memory::default(),
+            instruction_pointer: 0,
+            relative_base: 0,
+            steps: 0,
+            is_done: false,
+            program_length: 0,
+            inputs: Vec::new(),
+            output: Vec::new(),
+        };
+        let mut limit = None;
+        let mut cells = Vec::new();
+        let mut program_length_line = 0;
+        for (index, text) in lines {
+            let line = index + 1;
+            let (key, value) = match text.find(' ') {
+                Some(space) => (&text[..space], text[space + 1..].trim()),
+                None => (text.trim(), ""),
+            };
+            match key {
+                "ip" => snapshot.instruction_pointer = parse(value, line)?,
+                "relative_base" => snapshot.relative_base = parse(value, line)?,
+                "steps" => snapshot.steps = parse(value, line)?,
+                "halted" => snapshot.is_done = parse(value, line)?,
+                "program_length" => {
+                    snapshot.program_length = parse(value, line)?;
+                    program_length_line = line;
+                }
+                "memory_limit" if value == "none" => limit = None,
+                "memory_limit" => limit = Some(parse(value, line)?),
+                "inputs" => snapshot.inputs = parse_list(value, line)?,
+                "outputs" => snapshot.output = parse_list(value, line)?,
+                "memory" => snapshot.memory = Memory::new(parse_list(value, line)?),
+                "cell" => {
+                    let mut parts = value.split_whitespace();
+                    let address = parse(parts.next().unwrap_or(""), line)?;
+                    let value = parse(parts.next().unwrap_or(""), line)?;
+                    cells.push((address, value));
+                }
+                "" => {}
+                _ => {
+                    return Err(SnapshotError::Format {
+                        line,
+                        message: format!("unknown field {:?}", key),
+                    })
+                }
+            }
+        }
+
+        // the program is loaded at the start of memory, so it can't be any
+        // longer than what the snapshot holds there
+        if snapshot.program_length > snapshot.memory.dense().len() {
+            return Err(SnapshotError::Format {
+                line: program_length_line,
+                message: format!(
+                    "program_length {} is longer than the {} words of memory",
+                    snapshot.program_length,
+                    snapshot.memory.dense().len()
+                ),
+            });
+        }
+        for (address, value) in cells {
+            // no limit is set yet, so this can't fail
+            snapshot.memory.set(address, value).unwrap();
+        }
+        snapshot.memory.set_limit(limit);
+        Ok(snapshot)
+    }
+}
+
+impl<W: Word> Snapshot<W> {
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.to_string())
+    }
+
+    pub fn load(filename: &str) -> Result<Self, SnapshotError> {
+        fs::read_to_string(filename)?.parse()
+    }
+
+    /**
+     * The same snapshot with every value as an i64, None if one of them
+     * doesn't fit.
+     */
+    pub fn narrow(&self) -> Option<Snapshot> {
+        let narrow = |values: &[W]| values.iter().map(W::to_i64).collect::<Option<Vec<i64>>>();
+        Some(Snapshot {
+            memory: self.memory.try_map(W::to_i64)?,
+            instruction_pointer: self.instruction_pointer,
+            relative_base: self.relative_base,
+            steps: self.steps,
+            is_done: self.is_done,
+            program_length: self.program_length,
+            inputs: narrow(&self.inputs)?,
+            output: narrow(&self.output)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::int_code_computer::{IntCodeComputer, RunState};
+    use super::*;
+
+    #[test]
+    fn restores_a_running_computer() {
+        // doubles every input
+        let mut comp = IntCodeComputer::new("3,11,1002,11,2,11,4,11,1105,1,0").unwrap();
+        comp.add_inputs(vec![5, 6]);
+        assert_eq!(comp.run(), Ok(RunState::Output(10)));
+
+        let snapshot = comp.snapshot();
+        assert_eq!(comp.run(), Ok(RunState::Output(12)));
+        comp.restore(&snapshot);
+        assert_eq!(comp.pending_inputs(), &[6]);
+        assert_eq!(comp.run(), Ok(RunState::Output(12)));
+
+        let mut copy = comp.clone();
+        copy.add_inputs(vec![100]);
+        comp.add_inputs(vec![1]);
+        assert_eq!(copy.run(), Ok(RunState::Output(200)));
+        assert_eq!(comp.run(), Ok(RunState::Output(2)));
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut comp = IntCodeComputer::new("1101,5,6,1000000000,3,7,99,0").unwrap();
+        comp.set_memory_limit(Some(2_000_000_000));
+        comp.add_inputs(vec![4, -2]);
+        comp.process_code().unwrap();
+
+        let snapshot = comp.snapshot();
+        let text = snapshot.to_string();
+        assert!(text.contains("\ninputs -2\n"));
+        assert!(text.contains("\ncell 1000000000 11\n"));
+        assert_eq!(text.parse::<Snapshot>().unwrap(), snapshot);
+
+        let restored = IntCodeComputer::from_snapshot(text.parse::<Snapshot>().unwrap());
+        assert_eq!(restored.peek(1_000_000_000), 11);
+        assert_eq!(restored.peek(7), 4);
+        assert!(restored.is_done);
+    }
+
+    #[test]
+    fn rejects_bad_snapshots() {
+        match "intcode-snapshot 1\nip x".parse::<Snapshot>() {
+            Err(SnapshotError::Format { line, .. }) => assert_eq!(line, 2),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!("ip 4".parse::<Snapshot>().is_err());
+
+        // loading this used to run through every address up to the length
+        let text = "intcode-snapshot 1\nprogram_length 18446744073709551615\nmemory 99";
+        match text.parse::<Snapshot>() {
+            Err(SnapshotError::Format { line, .. }) => assert_eq!(line, 2),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,1173 @@
+use super::instruction::{decode, Mode, Opcode};
+use super::int_code_computer::{IntCodeComputer, IntCodeError};
+use super::limits::Limits;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+/**
+ * How far out a symbolic run may write, memory is a plain Vec here.
+ */
+const MAX_MEMORY: usize = 1 << 16;
+
+/**
+ * How many nodes the solver may look at when deciding whether a branch
+ * can be taken at all.
+ */
+const FORK_BUDGET: usize = 1_000;
+
+pub type Value = Rc<Expr>;
+
+/**
+ * A value computed from the unknowns of a program. Variables are numbered
+ * in the order of Problem::variables.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Const(i64),
+    Var(usize),
+    Add(Value, Value),
+    Mul(Value, Value),
+    LessThan(Value, Value),
+    Equals(Value, Value),
+    /**
+     * A read through an address that depends on the unknowns, from memory
+     * as it was at the time.
+     */
+    Load(Value, Rc<Vec<Value>>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Var(index) => write!(f, "x{}", index),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+            Expr::Load(address, _) => write!(f, "mem[{}]", address),
+        }
+    }
+}
+
+fn constant(value: i64) -> Value {
+    Rc::new(Expr::Const(value))
+}
+
+fn as_constant(value: &Value) -> Option<i64> {
+    match **value {
+        Expr::Const(value) => Some(value),
+        _ => None,
+    }
+}
+
+/**
+ * The arithmetic below folds constants as it goes and gives None where
+ * that overflows, the VM fails there too.
+ */
+fn add(a: &Value, b: &Value) -> Option<Value> {
+    Some(match (as_constant(a), as_constant(b)) {
+        (Some(x), Some(y)) => constant(x.checked_add(y)?),
+        (Some(0), _) => Rc::clone(b),
+        (_, Some(0)) => Rc::clone(a),
+        _ => Rc::new(Expr::Add(Rc::clone(a), Rc::clone(b))),
+    })
+}
+
+fn multiply(a: &Value, b: &Value) -> Option<Value> {
+    Some(match (as_constant(a), as_constant(b)) {
+        (Some(x), Some(y)) => constant(x.checked_mul(y)?),
+        (Some(0), _) | (_, Some(0)) => constant(0),
+        (Some(1), _) => Rc::clone(b),
+        (_, Some(1)) => Rc::clone(a),
+        _ => Rc::new(Expr::Mul(Rc::clone(a), Rc::clone(b))),
+    })
+}
+
+fn less_than(a: &Value, b: &Value) -> Value {
+    match (as_constant(a), as_constant(b)) {
+        (Some(x), Some(y)) => constant((x < y) as i64),
+        _ if a == b => constant(0),
+        _ => Rc::new(Expr::LessThan(Rc::clone(a), Rc::clone(b))),
+    }
+}
+
+fn equals(a: &Value, b: &Value) -> Value {
+    match (as_constant(a), as_constant(b)) {
+        (Some(x), Some(y)) => constant((x == y) as i64),
+        _ if a == b => constant(1),
+        _ => Rc::new(Expr::Equals(Rc::clone(a), Rc::clone(b))),
+    }
+}
+
+/**
+ * The value of `expr` with the variables set to `values`, None if the VM
+ * would fail computing it.
+ */
+pub fn evaluate(expr: &Expr, values: &[i64]) -> Option<i64> {
+    match expr {
+        Expr::Const(value) => Some(*value),
+        Expr::Var(index) => values.get(*index).copied(),
+        Expr::Add(a, b) => evaluate(a, values)?.checked_add(evaluate(b, values)?),
+        Expr::Mul(a, b) => evaluate(a, values)?.checked_mul(evaluate(b, values)?),
+        Expr::LessThan(a, b) => Some((evaluate(a, values)? < evaluate(b, values)?) as i64),
+        Expr::Equals(a, b) => Some((evaluate(a, values)? == evaluate(b, values)?) as i64),
+        Expr::Load(address, memory) => match evaluate(address, values)? {
+            address if address < 0 => None,
+            address => match memory.get(address as usize) {
+                Some(cell) => evaluate(cell, values),
+                None => Some(0),
+            },
+        },
+    }
+}
+
+fn variables(expr: &Expr, found: &mut BTreeSet<usize>) {
+    match expr {
+        Expr::Const(_) => {}
+        Expr::Var(index) => {
+            found.insert(*index);
+        }
+        Expr::Add(a, b) | Expr::Mul(a, b) | Expr::LessThan(a, b) | Expr::Equals(a, b) => {
+            variables(a, found);
+            variables(b, found);
+        }
+        Expr::Load(address, memory) => {
+            variables(address, found);
+            for cell in memory.iter() {
+                variables(cell, found);
+            }
+        }
+    }
+}
+
+/**
+ * constant + sum of coefficient * variable, with everything kept within
+ * i64 so products of two of them fit into an i128.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Linear {
+    constant: i128,
+    terms: BTreeMap<usize, i128>,
+}
+
+fn fits(value: i128) -> Option<i128> {
+    if value >= i64::MIN as i128 && value <= i64::MAX as i128 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+impl Linear {
+    fn of(expr: &Expr) -> Option<Linear> {
+        match expr {
+            Expr::Const(value) => Some(Linear {
+                constant: *value as i128,
+                terms: BTreeMap::new(),
+            }),
+            Expr::Var(index) => Some(Linear {
+                constant: 0,
+                terms: vec![(*index, 1)].into_iter().collect(),
+            }),
+            Expr::Add(a, b) => Linear::of(a)?.plus(&Linear::of(b)?, 1),
+            Expr::Mul(a, b) => {
+                let (a, b) = (Linear::of(a)?, Linear::of(b)?);
+                match (a.terms.is_empty(), b.terms.is_empty()) {
+                    (true, _) => b.times(a.constant),
+                    (_, true) => a.times(b.constant),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * self + factor * other, factor being 1 or -1.
+     */
+    fn plus(&self, other: &Linear, factor: i128) -> Option<Linear> {
+        let mut sum = self.clone();
+        sum.constant = fits(sum.constant + factor * other.constant)?;
+        for (index, coefficient) in other.terms.iter() {
+            let term = sum.terms.entry(*index).or_insert(0);
+            *term = fits(*term + factor * coefficient)?;
+        }
+        sum.terms.retain(|_, coefficient| *coefficient != 0);
+        Some(sum)
+    }
+
+    fn times(&self, factor: i128) -> Option<Linear> {
+        let mut product = Linear {
+            constant: fits(self.constant * factor)?,
+            terms: BTreeMap::new(),
+        };
+        for (index, coefficient) in self.terms.iter() {
+            product.terms.insert(*index, fits(coefficient * factor)?);
+        }
+        product.terms.retain(|_, coefficient| *coefficient != 0);
+        Some(product)
+    }
+
+    fn offset(&self, amount: i128) -> Option<Linear> {
+        let mut shifted = self.clone();
+        shifted.constant = fits(shifted.constant + amount)?;
+        Some(shifted)
+    }
+
+    /**
+     * Tightens `bounds` so that self <= 0 can still hold, giving whether
+     * anything changed, or None if it can't hold at all.
+     */
+    fn tighten(&self, bounds: &mut [(i64, i64)]) -> Option<bool> {
+        let smallest = |(min, max): (i64, i64), coefficient: i128| {
+            (coefficient * min as i128).min(coefficient * max as i128)
+        };
+        let mut minimum = Some(self.constant);
+        for (index, coefficient) in self.terms.iter() {
+            minimum =
+                minimum.and_then(|sum| sum.checked_add(smallest(bounds[*index], *coefficient)));
+        }
+        // too big to reason about, leave it to the final check
+        let minimum = match minimum {
+            Some(minimum) => minimum,
+            None => return Some(false),
+        };
+        if minimum > 0 {
+            return None;
+        }
+
+        let mut changed = false;
+        for (index, coefficient) in self.terms.iter() {
+            // coefficient * x <= -(everything else at its smallest)
+            let rest = smallest(bounds[*index], *coefficient) - minimum;
+            let (min, max) = bounds[*index];
+            if *coefficient > 0 {
+                let limit = floor_div(rest, *coefficient);
+                if limit < max as i128 {
+                    bounds[*index].1 = limit.max(i64::MIN as i128) as i64;
+                    changed = true;
+                }
+            } else {
+                let limit = ceil_div(rest, *coefficient);
+                if limit > min as i128 {
+                    bounds[*index].0 = limit.min(i64::MAX as i128) as i64;
+                    changed = true;
+                }
+            }
+            if bounds[*index].0 > bounds[*index].1 {
+                return None;
+            }
+        }
+        Some(changed)
+    }
+}
+
+fn floor_div(numerator: i128, denominator: i128) -> i128 {
+    let quotient = numerator / denominator;
+    if numerator % denominator != 0 && (numerator < 0) != (denominator < 0) {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+fn ceil_div(numerator: i128, denominator: i128) -> i128 {
+    -floor_div(-numerator, denominator)
+}
+
+/**
+ * What a path needs of the unknowns: `value` is nonzero if `holds`, zero
+ * otherwise.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Constraint {
+    pub value: Value,
+    pub holds: bool,
+}
+
+impl Constraint {
+    pub fn is_met(&self, values: &[i64]) -> bool {
+        evaluate(&self.value, values).is_some_and(|value| (value != 0) == self.holds)
+    }
+
+    /**
+     * The constraint as linear terms that must each be <= 0. Inequations
+     * and anything non-linear give nothing here and are only checked
+     * once all their variables are known.
+     */
+    fn linear(&self) -> Vec<Linear> {
+        let difference = |a: &Expr, b: &Expr| Linear::of(a)?.plus(&Linear::of(b)?, -1);
+        let terms = match (&*self.value, self.holds) {
+            (Expr::Equals(a, b), true) => difference(a, b).map(|d| vec![d.times(-1), Some(d)]),
+            (Expr::Equals(_, _), false) => None,
+            // a < b is a - b + 1 <= 0, a >= b is b - a <= 0
+            (Expr::LessThan(a, b), true) => difference(a, b).map(|d| vec![d.offset(1)]),
+            (Expr::LessThan(a, b), false) => difference(b, a).map(|d| vec![Some(d)]),
+            (value, false) => Linear::of(value).map(|d| vec![d.times(-1), Some(d)]),
+            (_, true) => None,
+        };
+        terms.unwrap_or_default().into_iter().flatten().collect()
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&*self.value, self.holds) {
+            (Expr::Equals(a, b), false) => write!(f, "{} != {}", a, b),
+            (Expr::LessThan(a, b), false) => write!(f, "{} >= {}", a, b),
+            (Expr::Equals(a, b), true) => write!(f, "{} == {}", a, b),
+            (Expr::LessThan(a, b), true) => write!(f, "{} < {}", a, b),
+            (value, true) => write!(f, "{} != 0", value),
+            (value, false) => write!(f, "{} == 0", value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Answer {
+    Sat(Vec<i64>),
+    Unsat,
+    /**
+     * Ran out of budget before deciding.
+     */
+    Unknown,
+}
+
+struct Search<'a> {
+    constraints: &'a [Constraint],
+    variables: Vec<BTreeSet<usize>>,
+    linear: Vec<Linear>,
+    budget: usize,
+}
+
+impl<'a> Search<'a> {
+    fn propagate(&self, bounds: &mut [(i64, i64)]) -> bool {
+        // each round only tightens, but huge domains could take forever
+        for _ in 0..64 {
+            let mut changed = false;
+            for linear in self.linear.iter() {
+                match linear.tighten(bounds) {
+                    Some(tightened) => changed |= tightened,
+                    None => return false,
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        // whatever is fully known can be checked right away
+        let values: Vec<i64> = bounds.iter().map(|(min, _)| *min).collect();
+        self.constraints
+            .iter()
+            .zip(self.variables.iter())
+            .filter(|(_, variables)| {
+                variables
+                    .iter()
+                    .all(|index| bounds[*index].0 == bounds[*index].1)
+            })
+            .all(|(constraint, _)| constraint.is_met(&values))
+    }
+
+    fn search(&mut self, mut bounds: Vec<(i64, i64)>) -> Answer {
+        if self.budget == 0 {
+            return Answer::Unknown;
+        }
+        self.budget -= 1;
+        if !self.propagate(&mut bounds) {
+            return Answer::Unsat;
+        }
+        let open = (0..bounds.len())
+            .filter(|index| bounds[*index].0 < bounds[*index].1)
+            .min_by_key(|index| bounds[*index].1 as i128 - bounds[*index].0 as i128);
+        let index = match open {
+            Some(index) => index,
+            None => return Answer::Sat(bounds.iter().map(|(min, _)| *min).collect()),
+        };
+
+        let (min, max) = bounds[index];
+        let middle = ((min as i128 + max as i128).div_euclid(2)) as i64;
+        let mut unknown = false;
+        for half in [(min, middle), (middle + 1, max)].iter() {
+            let mut bounds = bounds.clone();
+            bounds[index] = *half;
+            match self.search(bounds) {
+                Answer::Sat(values) => return Answer::Sat(values),
+                Answer::Unknown => unknown = true,
+                Answer::Unsat => {}
+            }
+        }
+        if unknown {
+            Answer::Unknown
+        } else {
+            Answer::Unsat
+        }
+    }
+}
+
+/**
+ * Finds values within `bounds` meeting every constraint. Linear
+ * constraints narrow the bounds down directly, the domains are split in
+ * half until that decides everything, so anything else is enumerated.
+ * Gives up with Unknown after looking at `budget` sets of bounds.
+ */
+pub fn solve_constraints(
+    bounds: &[(i64, i64)],
+    constraints: &[Constraint],
+    budget: usize,
+) -> Answer {
+    if bounds.iter().any(|(min, max)| min > max) {
+        return Answer::Unsat;
+    }
+    let mut search = Search {
+        constraints,
+        variables: constraints
+            .iter()
+            .map(|constraint| {
+                let mut found = BTreeSet::new();
+                variables(&constraint.value, &mut found);
+                found
+            })
+            .collect(),
+        linear: constraints
+            .iter()
+            .flat_map(|constraint| constraint.linear())
+            .collect(),
+        budget,
+    };
+    search.search(bounds.to_vec())
+}
+
+/**
+ * An unknown of a program, an integer from min to max.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Variable {
+    pub name: String,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl Variable {
+    pub fn new(name: &str, min: i64, max: i64) -> Self {
+        Self {
+            name: name.to_string(),
+            min,
+            max,
+        }
+    }
+}
+
+/**
+ * What the unknowns should make the program do.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Goal {
+    /**
+     * Halt with `value` at `address`.
+     */
+    Memory { address: usize, value: i64 },
+    /**
+     * Output `value` as output number `index`, counting from 0.
+     */
+    Output { index: usize, value: i64 },
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Goal::Memory { address, value } => write!(f, "mem[{}] = {}", address, value),
+            Goal::Output { index, value } => write!(f, "out[{}] = {}", index, value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Problem {
+    pub code: Vec<i64>,
+    /**
+     * Cells that hold an unknown instead of what the program has there,
+     * like the noun and verb of day 2.
+     */
+    pub cells: Vec<(usize, Variable)>,
+    /**
+     * The unknowns the program reads with IN, in order.
+     */
+    pub inputs: Vec<Variable>,
+    pub goal: Goal,
+}
+
+impl Problem {
+    /**
+     * The cells' unknowns followed by the inputs'.
+     */
+    pub fn variables(&self) -> Vec<&Variable> {
+        self.cells
+            .iter()
+            .map(|(_, variable)| variable)
+            .chain(self.inputs.iter())
+            .collect()
+    }
+}
+
+/**
+ * How a path through the program ended.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Ending {
+    Halted,
+    /**
+     * Produced the output the goal is about.
+     */
+    Output,
+    NeedsInput,
+    Failed,
+    StepLimit,
+    /**
+     * Did something the symbolic run can't follow, like jumping to an
+     * address that depends on the unknowns.
+     */
+    Unsupported {
+        ip: usize,
+        reason: &'static str,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path {
+    pub constraints: Vec<Constraint>,
+    pub ending: Ending,
+    /**
+     * What else it takes for the path to reach the goal, None if it
+     * can't.
+     */
+    pub goal: Option<Constraint>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Exploration {
+    pub paths: Vec<Path>,
+    /**
+     * Whether every path was followed to its end, so none can be missing.
+     */
+    pub complete: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Method {
+    Solver,
+    BruteForce,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Solution {
+    /**
+     * In the order of Problem::variables.
+     */
+    pub values: Vec<i64>,
+    pub method: Method,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolicError {
+    NoSolution,
+    /**
+     * Nothing reached the goal, but some runs were cut off before they
+     * could.
+     */
+    StepLimit {
+        steps: u64,
+    },
+    TooManyCandidates {
+        candidates: u128,
+        limit: u128,
+    },
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::NoSolution => write!(f, "no values reach the goal"),
+            SymbolicError::StepLimit { steps } => write!(
+                f,
+                "no values reach the goal within {} steps, some runs take longer",
+                steps
+            ),
+            SymbolicError::TooManyCandidates { candidates, limit } => write!(
+                f,
+                "the solver can't decide and {} candidates are too many to try, the limit is {}",
+                candidates, limit
+            ),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+#[derive(Clone)]
+struct State {
+    memory: Vec<Value>,
+    ip: usize,
+    relative_base: i64,
+    inputs: usize,
+    outputs: usize,
+    constraints: Vec<Constraint>,
+    steps: u64,
+}
+
+impl State {
+    fn end(self, ending: Ending, goal: Option<Constraint>) -> Path {
+        Path {
+            constraints: self.constraints,
+            ending,
+            goal,
+        }
+    }
+
+    fn read(&self, address: usize) -> Value {
+        match self.memory.get(address) {
+            Some(value) => Rc::clone(value),
+            None => constant(0),
+        }
+    }
+
+    fn load(&self, address: &Value) -> Result<Value, Ending> {
+        match as_constant(address) {
+            Some(address) if address < 0 => Err(Ending::Failed),
+            Some(address) => Ok(self.read(address as usize)),
+            None => Ok(Rc::new(Expr::Load(
+                Rc::clone(address),
+                Rc::new(self.memory.clone()),
+            ))),
+        }
+    }
+
+    /**
+     * What parameter `index` of the current instruction reads.
+     */
+    fn get(&self, index: usize, mode: Mode) -> Result<Value, Ending> {
+        let word = self.read(self.ip + index + 1);
+        match mode {
+            Mode::Immediate => Ok(word),
+            Mode::Position => self.load(&word),
+            Mode::Relative => {
+                let address = add(&word, &constant(self.relative_base)).ok_or(Ending::Failed)?;
+                self.load(&address)
+            }
+        }
+    }
+
+    /**
+     * Where parameter `index` of the current instruction writes to.
+     */
+    fn destination(&self, index: usize, mode: Mode) -> Result<usize, Ending> {
+        let word = as_constant(&self.read(self.ip + index + 1)).ok_or(Ending::Unsupported {
+            ip: self.ip,
+            reason: "writes through an unknown address",
+        })?;
+        let address = match mode {
+            Mode::Relative => word.checked_add(self.relative_base),
+            _ => Some(word),
+        };
+        match address {
+            Some(address) if address >= 0 => Ok(address as usize),
+            _ => Err(Ending::Failed),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: Value) -> Result<(), Ending> {
+        if address >= MAX_MEMORY {
+            return Err(Ending::Unsupported {
+                ip: self.ip,
+                reason: "writes too far out",
+            });
+        }
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, constant(0));
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+}
+
+/**
+ * Runs a program with unknowns in place of some of its cells and inputs.
+ * Arithmetic on them builds up expressions and every branch on one forks
+ * the run, the condition becoming a constraint of each path. Solving those
+ * constraints together with the goal gives the unknowns. When the solver
+ * can't decide, or the program does something a symbolic run can't
+ * follow, every combination of values is tried on IntCodeComputer.
+ */
+pub struct Engine {
+    pub problem: Problem,
+    /**
+     * Instructions per path, and per run when trying values.
+     */
+    pub max_steps: u64,
+    pub max_paths: usize,
+    /**
+     * Sets of bounds the solver may look at per path.
+     */
+    pub solver_budget: usize,
+    /**
+     * How many combinations of values may be tried before giving up.
+     */
+    pub max_candidates: u128,
+}
+
+impl Engine {
+    pub fn new(problem: Problem) -> Self {
+        Self {
+            problem,
+            max_steps: 100_000,
+            max_paths: 1_000,
+            solver_budget: 100_000,
+            max_candidates: 1_000_000,
+        }
+    }
+
+    fn bounds(&self) -> Vec<(i64, i64)> {
+        self.problem
+            .variables()
+            .iter()
+            .map(|variable| (variable.min, variable.max))
+            .collect()
+    }
+
+    /**
+     * Follows every path through the program, up to max_paths of them.
+     * Branches that can't be taken with the unknowns in their bounds are
+     * left out.
+     */
+    pub fn explore(&self) -> Exploration {
+        let mut memory: Vec<Value> = self
+            .problem
+            .code
+            .iter()
+            .map(|word| constant(*word))
+            .collect();
+        for (index, (address, _)) in self.problem.cells.iter().enumerate() {
+            if *address >= memory.len() {
+                memory.resize(address + 1, constant(0));
+            }
+            memory[*address] = Rc::new(Expr::Var(index));
+        }
+        let mut pending = vec![State {
+            memory,
+            ip: 0,
+            relative_base: 0,
+            inputs: 0,
+            outputs: 0,
+            constraints: Vec::new(),
+            steps: 0,
+        }];
+
+        let mut paths = Vec::new();
+        while let Some(state) = pending.pop() {
+            if paths.len() == self.max_paths {
+                break;
+            }
+            paths.push(self.follow(state, &mut pending));
+        }
+        let complete = pending.is_empty()
+            && paths
+                .iter()
+                .all(|path| !matches!(path.ending, Ending::StepLimit | Ending::Unsupported { .. }));
+        Exploration { paths, complete }
+    }
+
+    /**
+     * Runs `state` to the end of its path, pushing the other side of
+     * every branch it forks on to `pending`.
+     */
+    fn follow(&self, mut state: State, pending: &mut Vec<State>) -> Path {
+        let bounds = self.bounds();
+        loop {
+            if state.steps >= self.max_steps {
+                return state.end(Ending::StepLimit, None);
+            }
+            let ip = state.ip;
+            let word = match as_constant(&state.read(ip)) {
+                Some(word) => word,
+                None => {
+                    let ending = Ending::Unsupported {
+                        ip,
+                        reason: "runs an instruction that depends on the unknowns",
+                    };
+                    return state.end(ending, None);
+                }
+            };
+            let instruction = match decode(word, ip) {
+                Ok(instruction) => instruction,
+                Err(_) => return state.end(Ending::Failed, None),
+            };
+            let modes = instruction.modes;
+            state.steps += 1;
+
+            let executed = match instruction.opcode {
+                Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+                    let operands = state
+                        .get(0, modes[0])
+                        .and_then(|a| Ok((a, state.get(1, modes[1])?)));
+                    operands.and_then(|(a, b)| {
+                        let value = match instruction.opcode {
+                            Opcode::Add => add(&a, &b).ok_or(Ending::Failed)?,
+                            Opcode::Multiply => multiply(&a, &b).ok_or(Ending::Failed)?,
+                            Opcode::LessThan => less_than(&a, &b),
+                            _ => equals(&a, &b),
+                        };
+                        let address = state.destination(2, modes[2])?;
+                        state.write(address, value)?;
+                        state.ip += 4;
+                        Ok(())
+                    })
+                }
+                Opcode::Input => {
+                    if state.inputs == self.problem.inputs.len() {
+                        return state.end(Ending::NeedsInput, None);
+                    }
+                    let variable = Rc::new(Expr::Var(self.problem.cells.len() + state.inputs));
+                    state.destination(0, modes[0]).and_then(|address| {
+                        state.write(address, variable)?;
+                        state.inputs += 1;
+                        state.ip += 2;
+                        Ok(())
+                    })
+                }
+                Opcode::Output => match state.get(0, modes[0]) {
+                    Ok(value) => {
+                        if let Goal::Output {
+                            index,
+                            value: target,
+                        } = self.problem.goal
+                        {
+                            if state.outputs == index {
+                                let goal = Constraint {
+                                    value: equals(&value, &constant(target)),
+                                    holds: true,
+                                };
+                                return state.end(Ending::Output, Some(goal));
+                            }
+                        }
+                        state.outputs += 1;
+                        state.ip += 2;
+                        Ok(())
+                    }
+                    Err(ending) => Err(ending),
+                },
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    let operands = state
+                        .get(0, modes[0])
+                        .and_then(|a| Ok((a, state.get(1, modes[1])?)));
+                    operands.and_then(|(condition, target)| {
+                        let jumps_if = instruction.opcode == Opcode::JumpIfTrue;
+                        let target = match as_constant(&target) {
+                            Some(target) if target < 0 => return Err(Ending::Failed),
+                            Some(target) => target as usize,
+                            None => {
+                                return Err(Ending::Unsupported {
+                                    ip,
+                                    reason: "jumps to an address that depends on the unknowns",
+                                })
+                            }
+                        };
+                        match as_constant(&condition) {
+                            Some(value) if (value != 0) == jumps_if => state.ip = target,
+                            Some(_) => state.ip += 3,
+                            None => {
+                                let mut jumped = state.clone();
+                                jumped.ip = target;
+                                jumped.constraints.push(Constraint {
+                                    value: Rc::clone(&condition),
+                                    holds: jumps_if,
+                                });
+                                state.ip += 3;
+                                state.constraints.push(Constraint {
+                                    value: condition,
+                                    holds: !jumps_if,
+                                });
+                                let possible = |state: &State| {
+                                    solve_constraints(&bounds, &state.constraints, FORK_BUDGET)
+                                        != Answer::Unsat
+                                };
+                                match (possible(&jumped), possible(&state)) {
+                                    (true, true) => pending.push(jumped),
+                                    (true, false) => state = jumped,
+                                    (false, true) => {}
+                                    (false, false) => return Err(Ending::Failed),
+                                }
+                            }
+                        }
+                        Ok(())
+                    })
+                }
+                Opcode::AdjustRelativeBase => state.get(0, modes[0]).and_then(|value| {
+                    let adjusted = as_constant(&value)
+                        .ok_or(Ending::Unsupported {
+                            ip,
+                            reason: "moves the relative base by an unknown amount",
+                        })?
+                        .checked_add(state.relative_base)
+                        .ok_or(Ending::Failed)?;
+                    state.relative_base = adjusted;
+                    state.ip += 2;
+                    Ok(())
+                }),
+                Opcode::Halt => {
+                    let goal = match self.problem.goal {
+                        Goal::Memory { address, value } => Some(Constraint {
+                            value: equals(&state.read(address), &constant(value)),
+                            holds: true,
+                        }),
+                        Goal::Output { .. } => None,
+                    };
+                    return state.end(Ending::Halted, goal);
+                }
+            };
+            if let Err(ending) = executed {
+                return state.end(ending, None);
+            }
+        }
+    }
+
+    /**
+     * Whether running the program with `values` reaches the goal.
+     */
+    pub fn check(&self, values: &[i64]) -> bool {
+        self.try_values(values) == Some(true)
+    }
+
+    /**
+     * Like check(), but None if the run was cut off after max_steps.
+     */
+    fn try_values(&self, values: &[i64]) -> Option<bool> {
+        let mut computer = IntCodeComputer::from_program(self.problem.code.clone());
+        let cells: Vec<(usize, i64)> = self
+            .problem
+            .cells
+            .iter()
+            .zip(values.iter())
+            .map(|((address, _), value)| (*address, *value))
+            .collect();
+        if computer.patch(&cells).is_err() {
+            return Some(false);
+        }
+        computer.set_limits(Some(Limits {
+            instructions: Some(self.max_steps),
+            ..Limits::default()
+        }));
+        computer.add_inputs(values[self.problem.cells.len()..].to_vec());
+        let output = match computer.process_code() {
+            Ok(result) => result.output,
+            Err(IntCodeError::LimitReached { .. }) => return None,
+            Err(_) => return Some(false),
+        };
+        Some(match self.problem.goal {
+            Goal::Memory { address, value } => computer.is_done && computer.peek(address) == value,
+            Goal::Output { index, value } => output.get(index) == Some(&value),
+        })
+    }
+
+    /**
+     * Tries every combination of values, the first variable changing
+     * slowest.
+     */
+    pub fn brute_force(&self) -> Result<Solution, SymbolicError> {
+        let bounds = self.bounds();
+        let candidates = bounds.iter().fold(1u128, |product, (min, max)| {
+            product.saturating_mul((*max as i128 - *min as i128 + 1).max(0) as u128)
+        });
+        if candidates > self.max_candidates {
+            return Err(SymbolicError::TooManyCandidates {
+                candidates,
+                limit: self.max_candidates,
+            });
+        }
+        if candidates == 0 {
+            return Err(SymbolicError::NoSolution);
+        }
+
+        let mut values: Vec<i64> = bounds.iter().map(|(min, _)| *min).collect();
+        let mut cut_off = false;
+        loop {
+            match self.try_values(&values) {
+                Some(true) => {
+                    return Ok(Solution {
+                        values,
+                        method: Method::BruteForce,
+                    })
+                }
+                Some(false) => {}
+                None => cut_off = true,
+            }
+            // counts up like an odometer
+            let mut index = values.len();
+            loop {
+                if index == 0 {
+                    return Err(if cut_off {
+                        SymbolicError::StepLimit {
+                            steps: self.max_steps,
+                        }
+                    } else {
+                        SymbolicError::NoSolution
+                    });
+                }
+                index -= 1;
+                if values[index] < bounds[index].1 {
+                    values[index] += 1;
+                    break;
+                }
+                values[index] = bounds[index].0;
+            }
+        }
+    }
+
+    /**
+     * Finds values for the unknowns that reach the goal, with the solver
+     * if it can decide every path and by trying them all otherwise.
+     */
+    pub fn solve(&self) -> Result<Solution, SymbolicError> {
+        let exploration = self.explore();
+        let bounds = self.bounds();
+        let mut undecided = !exploration.complete;
+        for path in exploration.paths.iter() {
+            let goal = match &path.goal {
+                Some(goal) => goal,
+                None => continue,
+            };
+            let mut constraints = path.constraints.clone();
+            constraints.push(goal.clone());
+            match solve_constraints(&bounds, &constraints, self.solver_budget) {
+                Answer::Sat(values) if self.check(&values) => {
+                    return Ok(Solution {
+                        values,
+                        method: Method::Solver,
+                    })
+                }
+                Answer::Unsat => {}
+                _ => undecided = true,
+            }
+        }
+        if undecided {
+            self.brute_force()
+        } else {
+            Err(SymbolicError::NoSolution)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::int_code_computer::read_code;
+    use super::*;
+    use std::fs;
+
+    fn solve(
+        code: &str,
+        cells: Vec<(usize, Variable)>,
+        inputs: Vec<Variable>,
+        goal: Goal,
+    ) -> Result<Solution, SymbolicError> {
+        Engine::new(Problem {
+            code: read_code(code).unwrap(),
+            cells,
+            inputs,
+            goal,
+        })
+        .solve()
+    }
+
+    #[test]
+    fn solves_day_2_for_noun_and_verb() {
+        let code = fs::read_to_string("./src/exercises/data/data-day2.txt").unwrap();
+        let cells = vec![
+            (1, Variable::new("noun", 0, 99)),
+            (2, Variable::new("verb", 0, 99)),
+        ];
+        let goal = Goal::Memory {
+            address: 0,
+            value: 19690720,
+        };
+        let engine = Engine::new(Problem {
+            code: read_code(&code).unwrap(),
+            cells,
+            inputs: Vec::new(),
+            goal,
+        });
+        let exploration = engine.explore();
+        assert!(exploration.complete);
+        assert_eq!(exploration.paths.len(), 1);
+
+        let solution = engine.solve().unwrap();
+        assert_eq!(solution.method, Method::Solver);
+        assert_eq!(solution.values, vec![49, 67]);
+    }
+
+    #[test]
+    fn forks_on_branches_over_inputs() {
+        // outputs whether the input equals 8
+        let code = "3,9,8,9,10,9,4,9,99,-1,8";
+        let x = || vec![Variable::new("x", -100, 100)];
+        let equal = solve(code, vec![], x(), Goal::Output { index: 0, value: 1 });
+        assert_eq!(
+            equal,
+            Ok(Solution {
+                values: vec![8],
+                method: Method::Solver
+            })
+        );
+        let unequal = solve(code, vec![], x(), Goal::Output { index: 0, value: 0 });
+        assert_eq!(unequal.map(|solution| solution.values[0] != 8), Ok(true));
+        let impossible = solve(code, vec![], x(), Goal::Output { index: 0, value: 2 });
+        assert_eq!(impossible, Err(SymbolicError::NoSolution));
+
+        // x * x == 49 isn't linear, splitting the bounds still gets there
+        let square = solve(
+            "3,0,2,0,0,0,4,0,99",
+            vec![],
+            vec![Variable::new("x", 0, 20)],
+            Goal::Output {
+                index: 0,
+                value: 49,
+            },
+        );
+        assert_eq!(
+            square,
+            Ok(Solution {
+                values: vec![7],
+                method: Method::Solver
+            })
+        );
+    }
+
+    #[test]
+    fn falls_back_to_trying_every_value() {
+        // runs its input as an instruction, OUT #5 is what it takes
+        let code = "3,2,0,5,99";
+        let goal = Goal::Output { index: 0, value: 5 };
+        let solved = solve(code, vec![], vec![Variable::new("x", 0, 200)], goal);
+        assert_eq!(
+            solved,
+            Ok(Solution {
+                values: vec![104],
+                method: Method::BruteForce
+            })
+        );
+        let mut engine = Engine::new(Problem {
+            code: read_code(code).unwrap(),
+            cells: vec![],
+            inputs: vec![Variable::new("x", 0, i64::MAX)],
+            goal,
+        });
+        engine.max_candidates = 1000;
+        assert_eq!(
+            engine.solve(),
+            Err(SymbolicError::TooManyCandidates {
+                candidates: i64::MAX as u128 + 1,
+                limit: 1000
+            })
+        );
+    }
+}
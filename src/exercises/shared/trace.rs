@@ -0,0 +1,201 @@
+use super::instruction::{Instruction, Opcode};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+
+/**
+ * One executed instruction. `operands` holds the values that were read,
+ * or the destination address for a parameter that gets written to.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent<W = i64> {
+    pub step: u64,
+    pub ip: usize,
+    pub instruction: Instruction,
+    pub operands: Vec<W>,
+    pub write: Option<(usize, W)>,
+}
+
+pub type TraceCallback<W = i64> = Box<dyn FnMut(&TraceEvent<W>) + Send>;
+
+impl<W: fmt::Display> fmt::Display for TraceEvent<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>8} {:>6}  {:<4}",
+            self.step,
+            self.ip,
+            self.instruction.opcode.mnemonic()
+        )?;
+        let operands: Vec<String> = self
+            .operands
+            .iter()
+            .map(|value| value.to_string())
+            .collect();
+        write!(f, "{}", operands.join(", "))?;
+        if let Some((address, value)) = &self.write {
+            write!(f, "  -> {} = {}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * A tracer that writes one line per instruction, e.g. to a file.
+ * Write errors are ignored so a full disk doesn't stop the program.
+ */
+pub fn write_trace<W: Write + Send + 'static>(mut writer: W) -> TraceCallback {
+    Box::new(move |event: &TraceEvent| {
+        let _ = writeln!(writer, "{}", event);
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HotLoop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: u64,
+    pub instructions: u64,
+}
+
+/**
+ * Execution counts per address and per opcode. Every jump backwards is
+ * counted as one iteration of a loop spanning the target to the jump.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub by_address: HashMap<usize, u64>,
+    pub by_opcode: HashMap<Opcode, u64>,
+    pub back_edges: HashMap<(usize, usize), u64>,
+}
+
+impl Profile {
+    pub fn record(&mut self, ip: usize, opcode: Opcode, next_ip: usize) {
+        *self.by_address.entry(ip).or_insert(0) += 1;
+        *self.by_opcode.entry(opcode).or_insert(0) += 1;
+        if next_ip <= ip {
+            *self.back_edges.entry((next_ip, ip)).or_insert(0) += 1;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.by_opcode.values().sum()
+    }
+
+    /**
+     * Loops ordered by how many instructions were executed inside them.
+     */
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self
+            .back_edges
+            .iter()
+            .map(|(&(start, end), &iterations)| HotLoop {
+                start,
+                end,
+                iterations,
+                instructions: self
+                    .by_address
+                    .iter()
+                    .filter(|(address, _)| (start..=end).contains(*address))
+                    .map(|(_, count)| count)
+                    .sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then(a.start.cmp(&b.start))
+        });
+        loops
+    }
+
+    /**
+     * A human readable summary with the `top` busiest addresses and loops.
+     */
+    pub fn report(&self, top: usize) -> String {
+        let mut lines = vec![format!("instructions executed: {}", self.total())];
+
+        let mut opcodes: Vec<(&Opcode, &u64)> = self.by_opcode.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        lines.push(String::from("by opcode:"));
+        for (opcode, count) in opcodes {
+            lines.push(format!("  {:<4}{:>12}", opcode.mnemonic(), count));
+        }
+
+        let mut addresses: Vec<(&usize, &u64)> = self.by_address.iter().collect();
+        addresses.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        lines.push(String::from("hottest addresses:"));
+        for (address, count) in addresses.into_iter().take(top) {
+            lines.push(format!("  {:>6}{:>12}", address, count));
+        }
+
+        lines.push(String::from("hot loops:"));
+        for hot_loop in self.hot_loops().into_iter().take(top) {
+            lines.push(format!(
+                "  {:>6}..{:<6} {:>10} iterations {:>12} instructions",
+                hot_loop.start, hot_loop.end, hot_loop.iterations, hot_loop.instructions
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble_to_string;
+    use super::super::int_code_computer::IntCodeComputer;
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    const COUNTDOWN: &str = "
+                IN n
+        loop:   ADD n, #-1, n
+                JT n, #loop
+                OUT n
+                HLT
+        n:      .data 0
+    ";
+
+    #[test]
+    fn traces_each_instruction() {
+        let mut comp = IntCodeComputer::new(&assemble_to_string(COUNTDOWN).unwrap()).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        comp.set_tracer(Some(Box::new(move |event: &TraceEvent| {
+            recorded.lock().unwrap().push(event.clone())
+        })));
+        comp.add_inputs(vec![2]);
+        comp.process_code().unwrap();
+
+        let events = events.lock().unwrap();
+        let lines: Vec<String> = events.iter().map(|event| event.to_string()).collect();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0].write, Some((12, 2)));
+        assert_eq!(events[1].operands, vec![2, -1, 12]);
+        assert_eq!(events[1].write, Some((12, 1)));
+        assert_eq!(lines[2], "       2      6  JT  1, 2");
+        assert_eq!(events[5].instruction.opcode, Opcode::Output);
+    }
+
+    #[test]
+    fn profiles_addresses_opcodes_and_loops() {
+        let mut comp = IntCodeComputer::new(&assemble_to_string(COUNTDOWN).unwrap()).unwrap();
+        comp.enable_profiling();
+        comp.add_inputs(vec![10]);
+        comp.process_code().unwrap();
+
+        let profile = comp.profile().unwrap();
+        assert_eq!(profile.total(), 22);
+        assert_eq!(profile.by_address[&2], 10);
+        assert_eq!(profile.by_opcode[&Opcode::JumpIfTrue], 10);
+        assert_eq!(
+            profile.hot_loops(),
+            vec![HotLoop {
+                start: 2,
+                end: 6,
+                iterations: 9,
+                instructions: 20,
+            }]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use super::int_code_computer::IntCodeComputer;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::fmt;
+use std::str::FromStr;
+
+/**
+ * A value an IntCodeComputer can compute with, i64 unless a program
+ * outgrows it. Opcodes and addresses still have to fit into an i64,
+ * anything else only has to fit into the type.
+ */
+pub trait Word: Clone + Default + fmt::Debug + fmt::Display + Ord + FromStr {
+    fn from_i64(value: i64) -> Self;
+    fn to_i64(&self) -> Option<i64>;
+    fn checked_sum(&self, other: &Self) -> Option<Self>;
+    fn checked_product(&self, other: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+pub type I128Computer = IntCodeComputer<i128>;
+pub type BigComputer = IntCodeComputer<BigInt>;
+
+impl Word for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn checked_sum(&self, other: &Self) -> Option<Self> {
+        self.checked_add(*other)
+    }
+
+    fn checked_product(&self, other: &Self) -> Option<Self> {
+        self.checked_mul(*other)
+    }
+}
+
+impl Word for i128 {
+    fn from_i64(value: i64) -> Self {
+        i128::from(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_sum(&self, other: &Self) -> Option<Self> {
+        self.checked_add(*other)
+    }
+
+    fn checked_product(&self, other: &Self) -> Option<Self> {
+        self.checked_mul(*other)
+    }
+}
+
+/**
+ * Never overflows, at the cost of an allocation for most operations.
+ */
+impl Word for BigInt {
+    fn from_i64(value: i64) -> Self {
+        BigInt::from(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_sum(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_product(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::int_code_computer::{read_code, IntCodeError, RunState};
+    use super::super::limits::{Limit, Limits};
+    use super::*;
+
+    // squares its input and then squares the result again
+    const SQUARE_TWICE: &str = "3,13,2,13,13,13,2,13,13,13,4,13,99,0";
+
+    #[test]
+    fn computes_past_i64() {
+        let mut comp = I128Computer::parse(SQUARE_TWICE).unwrap();
+        comp.add_inputs(vec![100_000]);
+        assert_eq!(
+            comp.process_code().map(|result| result.output),
+            Ok(vec![100_000_000_000_000_000_000])
+        );
+
+        let mut comp = IntCodeComputer::new(SQUARE_TWICE).unwrap();
+        comp.add_inputs(vec![100_000]);
+        assert_eq!(comp.process_code(), Err(IntCodeError::Overflow { ip: 6 }));
+    }
+
+    #[test]
+    fn big_integers_never_overflow() {
+        let mut comp = BigComputer::parse(SQUARE_TWICE).unwrap();
+        let input: BigInt = "100000000000000000000".parse().unwrap();
+        comp.add_inputs(vec![input]);
+        let expected: BigInt = format!("1{}", "0".repeat(80)).parse().unwrap();
+        assert_eq!(
+            comp.process_code().map(|result| result.output),
+            Ok(vec![expected])
+        );
+
+        let mut comp = I128Computer::parse(SQUARE_TWICE).unwrap();
+        comp.add_inputs(vec![100_000_000_000]);
+        assert_eq!(comp.process_code(), Err(IntCodeError::Overflow { ip: 6 }));
+    }
+
+    #[test]
+    fn runs_the_day_9_quine() {
+        let message = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let mut comp = BigComputer::parse(message).unwrap();
+        let output: Vec<i64> = comp
+            .process_code()
+            .unwrap()
+            .output
+            .iter()
+            .map(|value| Word::to_i64(value).unwrap())
+            .collect();
+        assert_eq!(output, read_code(message).unwrap());
+    }
+
+    #[test]
+    fn wide_words_step_back_and_hit_limits() {
+        let mut comp = I128Computer::parse(SQUARE_TWICE).unwrap();
+        comp.set_history(Some(10));
+        comp.add_inputs(vec![100_000]);
+        assert_eq!(
+            comp.run(),
+            Ok(RunState::Output(100_000_000_000_000_000_000))
+        );
+        assert!(comp.step_back());
+        assert!(comp.step_back());
+        assert_eq!(comp.peek(13), 10_000_000_000);
+
+        comp.set_limits(Some(Limits {
+            outputs: Some(0),
+            ..Limits::default()
+        }));
+        match comp.run() {
+            Err(IntCodeError::LimitReached {
+                ip: 10,
+                limit: Limit::Outputs(0),
+                snapshot: None,
+            }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
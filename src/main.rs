@@ -1,8 +1,72 @@
+use advent_of_code::exercises::days;
+use advent_of_code::exercises::shared::ascii::AsciiComputer;
+use advent_of_code::exercises::shared::debugger::Debugger;
+use advent_of_code::exercises::shared::int_code_computer::read_code;
+use advent_of_code::exercises::shared::int_code_computer::IntCodeComputer;
+use advent_of_code::exercises::shared::intcode_io;
+use advent_of_code::exercises::shared::{
+    analyzer, assembler, bench, compiler, decompiler, disassembler, fuzzer, optimizer, symbolic,
+};
+use advent_of_code::utils;
 use std::env;
-mod exercises;
-mod utils;
+use std::io;
+use std::panic;
+use std::path::PathBuf;
 
-use exercises::days;
+/**
+ * Loads the Intcode program named by the second argument, printing the
+ * usage or the parse error if that doesn't work out.
+ */
+fn load_computer(args: &[String], usage: &str) -> Option<IntCodeComputer> {
+    let filename = match args.get(2) {
+        Some(filename) => filename,
+        None => {
+            println!("usage: cargo run {}", usage);
+            return None;
+        }
+    };
+    match IntCodeComputer::new(&utils::read_file_into_string(filename)) {
+        Ok(computer) => Some(computer),
+        Err(error) => {
+            println!("{}", error);
+            None
+        }
+    }
+}
+
+/**
+ * Reads `mem:<addr>=<value>` or `out:<index>=<value>`.
+ */
+fn parse_goal(text: &str) -> Option<symbolic::Goal> {
+    let (kind, rest) = text.split_at(text.find(':')?);
+    let (place, value) = rest[1..].split_at(rest.find('=')? - 1);
+    let (place, value) = (place.parse().ok()?, value[1..].parse().ok()?);
+    match kind {
+        "mem" => Some(symbolic::Goal::Memory {
+            address: place,
+            value,
+        }),
+        "out" => Some(symbolic::Goal::Output {
+            index: place,
+            value,
+        }),
+        _ => None,
+    }
+}
+
+/**
+ * Reads `<addr>=<min>..<max>` for a cell or `in=<min>..<max>` for the
+ * next input.
+ */
+fn parse_unknown(text: &str) -> Option<(Option<usize>, symbolic::Variable)> {
+    let (place, range) = text.split_at(text.find('=')?);
+    let (min, max) = range[1..].split_at(range.find("..")? - 1);
+    let variable = symbolic::Variable::new(place, min.parse().ok()?, max[2..].parse().ok()?);
+    match place {
+        "in" => Some((None, variable)),
+        _ => Some((Some(place.parse().ok()?), variable)),
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -39,6 +103,208 @@ fn main() {
             "day10" => {
                 days::day10::main();
             }
+            "asm" => match args.get(2) {
+                Some(filename) => {
+                    match assembler::assemble_to_string(&utils::read_file_into_string(filename)) {
+                        Ok(code) => println!("{}", code),
+                        Err(error) => println!("{}", error),
+                    }
+                }
+                None => println!("usage: cargo run asm <assembly file>"),
+            },
+            "debug" => {
+                if let Some(computer) = load_computer(&args, "debug <program file>") {
+                    let stdin = io::stdin();
+                    if let Err(error) = Debugger::new(computer).repl(stdin.lock(), io::stdout()) {
+                        println!("error: {}", error);
+                    }
+                }
+            }
+            "run" => {
+                if let Some(mut computer) = load_computer(&args, "run <program file>") {
+                    if let Err(error) = computer.run_io(&mut intcode_io::stdio()) {
+                        println!("error: {}", error);
+                    }
+                }
+            }
+            "ascii" => {
+                if let Some(computer) = load_computer(&args, "ascii <program file>") {
+                    let stdin = io::stdin();
+                    let mut ascii = AsciiComputer::new(computer);
+                    if let Err(error) = ascii.interact(stdin.lock(), io::stdout()) {
+                        println!("error: {}", error);
+                    }
+                }
+            }
+            "bench" => {
+                let day7 = utils::read_file_into_string("./src/exercises/data/data-day7.txt");
+                let day9 = utils::read_file_into_string("./src/exercises/data/data-day9.txt");
+                let comparison = bench::compare_with("day 7 feedback loops", 20, |interpreter| {
+                    bench::feedback_circuits(&day7, interpreter)
+                });
+                match comparison {
+                    Ok(comparison) => println!("{}", comparison),
+                    Err(error) => println!("day 7: {}", error),
+                }
+                for (input, runs) in [(1, 2000), (2, 5)].iter() {
+                    let name = format!("day 9 with input {}", input);
+                    match bench::compare(&name, &day9, &[*input], *runs) {
+                        Ok(comparison) => println!("{}", comparison),
+                        Err(error) => println!("{}: {}", name, error),
+                    }
+                }
+            }
+            "profile" => {
+                let usage = "profile <program file> [inputs...]";
+                if let Some(mut computer) = load_computer(&args, usage) {
+                    let inputs: Result<Vec<i64>, _> =
+                        args[3..].iter().map(|arg| arg.parse()).collect();
+                    match inputs {
+                        Ok(inputs) => computer.add_inputs(inputs),
+                        Err(_) => {
+                            println!("usage: cargo run {}", usage);
+                            return;
+                        }
+                    }
+                    computer.enable_profiling();
+                    match computer.process_code() {
+                        Ok(result) => println!("output: {:?}", result.output),
+                        Err(error) => println!("error: {}", error),
+                    }
+                    println!("{}", computer.profile().unwrap().report(10));
+                }
+            }
+            "compile" => match args.get(2) {
+                Some(filename) => match read_code(&utils::read_file_into_string(filename)) {
+                    Ok(code) => {
+                        let name = args.get(3).map_or("Compiled", |name| &name[..]);
+                        print!("{}", compiler::compile(&code, name))
+                    }
+                    Err(error) => println!("{}", error),
+                },
+                None => println!("usage: cargo run compile <program file> [struct name]"),
+            },
+            "analyze" => match args.get(2) {
+                Some(filename) => match read_code(&utils::read_file_into_string(filename)) {
+                    Ok(code) => {
+                        let analysis = analyzer::analyze(&code);
+                        match args.get(3).map(|format| &format[..]) {
+                            Some("dot") => print!("{}", analysis.to_dot(&code)),
+                            _ => print!("{}", analysis),
+                        }
+                    }
+                    Err(error) => println!("{}", error),
+                },
+                None => println!("usage: cargo run analyze <program file> [dot]"),
+            },
+            "decompile" => match args.get(2) {
+                Some(filename) => match read_code(&utils::read_file_into_string(filename)) {
+                    Ok(code) => print!("{}", decompiler::decompile(&code)),
+                    Err(error) => println!("{}", error),
+                },
+                None => println!("usage: cargo run decompile <program file>"),
+            },
+            "optimize" => match args.get(2) {
+                Some(filename) => match read_code(&utils::read_file_into_string(filename)) {
+                    Ok(code) => {
+                        let optimized = optimizer::optimize(&code);
+                        for rewrite in optimized.rewrites.iter() {
+                            println!("// {}", rewrite);
+                        }
+                        let words: Vec<String> =
+                            optimized.code.iter().map(|word| word.to_string()).collect();
+                        println!("{}", words.join(","));
+                    }
+                    Err(error) => println!("{}", error),
+                },
+                None => println!("usage: cargo run optimize <program file>"),
+            },
+            "fuzz" => {
+                let iterations = args.get(2).map_or(Ok(100_000), |arg| arg.parse());
+                let seed = args.get(3).map_or(Ok(2019), |arg| arg.parse());
+                let (iterations, seed) = match (iterations, seed) {
+                    (Ok(iterations), Ok(seed)) => (iterations, seed),
+                    _ => {
+                        println!("usage: cargo run fuzz [iterations] [seed] [output directory]");
+                        return;
+                    }
+                };
+                let mut fuzzer = fuzzer::Fuzzer::new(seed);
+                fuzzer.set_output(args.get(4).map(PathBuf::from));
+                for day in [2, 5, 7, 9].iter() {
+                    let filename = format!("./src/exercises/data/data-day{}.txt", day);
+                    let code = read_code(&utils::read_file_into_string(&filename)).unwrap();
+                    fuzzer.add_seed(fuzzer::Case {
+                        code,
+                        inputs: vec![1],
+                    });
+                }
+                // crashes are reported below, no need for every backtrace
+                panic::set_hook(Box::new(|_| {}));
+                match fuzzer.run(iterations) {
+                    Ok(report) => {
+                        println!(
+                            "{} runs, {} sites covered, {} cases kept, {} crashes",
+                            report.runs,
+                            report.coverage,
+                            report.corpus,
+                            report.crashes.len()
+                        );
+                        for crash in report.crashes.iter() {
+                            print!("{}: {}", crash.message, crash.case.to_text());
+                        }
+                    }
+                    Err(error) => println!("error: {}", error),
+                }
+            }
+            "solve" => {
+                let usage = "usage: cargo run solve <program file> <mem:addr=value|out:index=value> [addr=min..max|in=min..max ...]";
+                let code = args
+                    .get(2)
+                    .map(|filename| read_code(&utils::read_file_into_string(filename)));
+                let goal = args.get(3).and_then(|goal| parse_goal(goal));
+                let unknowns: Option<Vec<_>> = args
+                    .iter()
+                    .skip(4)
+                    .map(|unknown| parse_unknown(unknown))
+                    .collect();
+                match (code, goal, unknowns) {
+                    (Some(Ok(code)), Some(goal), Some(unknowns)) => {
+                        let mut problem = symbolic::Problem {
+                            code,
+                            cells: Vec::new(),
+                            inputs: Vec::new(),
+                            goal,
+                        };
+                        for (cell, variable) in unknowns {
+                            match cell {
+                                Some(address) => problem.cells.push((address, variable)),
+                                None => problem.inputs.push(variable),
+                            }
+                        }
+                        let engine = symbolic::Engine::new(problem);
+                        match engine.solve() {
+                            Ok(solution) => {
+                                println!("found with {:?}", solution.method);
+                                let variables = engine.problem.variables();
+                                for (variable, value) in variables.iter().zip(solution.values) {
+                                    println!("{}: {}", variable.name, value);
+                                }
+                            }
+                            Err(error) => println!("{}", error),
+                        }
+                    }
+                    (Some(Err(error)), _, _) => println!("{}", error),
+                    _ => println!("{}", usage),
+                }
+            }
+            "disasm" => match args.get(2) {
+                Some(filename) => match read_code(&utils::read_file_into_string(filename)) {
+                    Ok(code) => print!("{}", disassembler::listing(&code)),
+                    Err(error) => println!("{}", error),
+                },
+                None => println!("usage: cargo run disasm <program file>"),
+            },
             _ => (),
         }
     } else {
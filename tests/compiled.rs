@@ -0,0 +1,56 @@
+// build.rs compiles the day 7 and day 9 programs the way `cargo run
+// compile` does, these check the result against what the interpreter does
+// with them.
+use advent_of_code::exercises::shared::int_code_computer::IntCodeComputer;
+use advent_of_code::utils;
+use permutator::Permutation;
+
+#[allow(dead_code, unused_macros, clippy::all)]
+mod day7 {
+    include!(concat!(env!("OUT_DIR"), "/day7.rs"));
+}
+
+#[allow(dead_code, unused_macros, clippy::all)]
+mod day9 {
+    include!(concat!(env!("OUT_DIR"), "/day9.rs"));
+}
+
+fn data(day: u32) -> String {
+    utils::read_file_into_string(&format!("./src/exercises/data/data-day{}.txt", day))
+}
+
+fn interpret(message: &str, inputs: Vec<i64>) -> Vec<i64> {
+    let mut computer = IntCodeComputer::new(message).unwrap();
+    computer.add_inputs(inputs);
+    computer.process_code().unwrap().output
+}
+
+#[test]
+fn day7_amplifiers_match_the_interpreter() {
+    let message = data(7);
+    for phase_settings in [0, 1, 2, 3, 4].permutation() {
+        let (mut compiled, mut interpreted) = (0, 0);
+        for phase_setting in phase_settings {
+            let mut amplifier = day7::Day7::new();
+            amplifier.add_inputs(vec![phase_setting, compiled]);
+            compiled = amplifier.process_code().unwrap()[0];
+            assert!(!amplifier.is_interpreted());
+            interpreted = interpret(&message, vec![phase_setting, interpreted])[0];
+        }
+        assert_eq!(compiled, interpreted);
+    }
+}
+
+#[test]
+fn day9_matches_the_interpreter() {
+    let message = data(9);
+    for input in [1, 2].iter() {
+        let mut compiled = day9::Day9::new();
+        compiled.add_inputs(vec![*input]);
+        assert_eq!(
+            compiled.process_code().unwrap(),
+            interpret(&message, vec![*input])
+        );
+        assert!(!compiled.is_interpreted());
+    }
+}